@@ -96,8 +96,8 @@ class Device:
         """Init Pica device"""
         r = requests.post(f'{self.http_address}/init-uci-device/{mac_address}',
             data=json.dumps({
-                'x': int(x), 'y': int(y), 'z': int(z),
-                'yaw': int(yaw), 'pitch': int(pitch), 'roll': int(roll)
+                'x': float(x), 'y': float(y), 'z': float(z),
+                'yaw': float(yaw), 'pitch': float(pitch), 'roll': float(roll)
             }))
         print(f'{r.status_code}: {r.text}')
 
@@ -114,8 +114,8 @@ class Device:
         """Create a Pica anchor"""
         r = requests.post(f'{self.http_address}/create-anchor/{mac_address}',
             data=json.dumps({
-                'x': int(x), 'y': int(y), 'z': int(z),
-                'yaw': int(yaw), 'pitch': int(pitch), 'roll': int(roll)
+                'x': float(x), 'y': float(y), 'z': float(z),
+                'yaw': float(yaw), 'pitch': float(pitch), 'roll': float(roll)
             }))
         print(f'{r.status_code}: {r.text}')
 
@@ -140,8 +140,8 @@ class Device:
         """Set Pica UCI device or anchor position"""
         r = requests.post(f'{self.http_address}/set-position/{mac_address}',
             data=json.dumps({
-                'x': int(x), 'y': int(y), 'z': int(z),
-                'yaw': int(yaw), 'pitch': int(pitch), 'roll': int(roll)
+                'x': float(x), 'y': float(y), 'z': float(z),
+                'yaw': float(yaw), 'pitch': float(pitch), 'roll': float(roll)
             }))
         print(f'{r.status_code}: {r.text}')
 
@@ -0,0 +1,360 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Virtual UWBS endpoint simulated by an anchor.
+
+use crate::clock::Clock;
+use crate::session::{
+    ChannelNumber, DeviceRole, DeviceType, MacAddressMode, Session, DEFAULT_RANGING_INTERVAL,
+};
+use crate::{MacAddress, PicaCommand, Position};
+use hex::FromHex;
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const STS_KEY_SIZE: usize = 8;
+
+/// Static STS key, made of the vendor id followed by the static STS
+/// initialization vector. cf. [UCI] 8.3 Table 29
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct StsKey(pub [u8; STS_KEY_SIZE]);
+
+impl TryFrom<String> for StsKey {
+    type Error = String;
+    fn try_from(key: String) -> Result<Self, String> {
+        <[u8; STS_KEY_SIZE]>::from_hex(key.replace(':', ""))
+            .map(StsKey)
+            .map_err(|err| format!("invalid STS key {}: {}", key, err))
+    }
+}
+
+impl From<StsKey> for String {
+    fn from(key: StsKey) -> Self {
+        hex::encode_upper(key.0)
+    }
+}
+
+impl Display for StsKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", String::from(*self))
+    }
+}
+
+/// Configuration of an anchor. Measurements are produced for a
+/// controller session only when the session matches the configuration.
+/// Initiator anchors act as controllers, and range on their own schedule
+/// with the controlee sessions matching the configuration.
+/// The default configuration matches any session with short addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnchorConfig {
+    /// Ranging role of the anchor. Sessions configuring the same
+    /// role on the controller side are rejected.
+    pub role: DeviceRole,
+    /// Supported UWB channel numbers.
+    pub channels: Vec<u8>,
+    /// Accepted session ids. All sessions are accepted when empty.
+    pub session_ids: Vec<u32>,
+    /// Accepted static STS keys. All keys are accepted when empty.
+    pub sts_keys: Vec<StsKey>,
+    /// MAC address mode, as defined by the MAC_ADDRESS_MODE
+    /// app config parameter.
+    pub mac_address_mode: u8,
+    /// Unpowered anchors do not respond to any session.
+    pub powered: bool,
+    /// Ranging interval of initiator anchors, in milliseconds.
+    pub ranging_interval: u32,
+}
+
+impl Default for AnchorConfig {
+    fn default() -> Self {
+        AnchorConfig {
+            role: DeviceRole::Responder,
+            channels: vec![5, 6, 8, 9, 10, 12, 13, 14],
+            session_ids: Vec::new(),
+            sts_keys: Vec::new(),
+            mac_address_mode: MacAddressMode::AddressMode0 as u8,
+            powered: true,
+            ranging_interval: DEFAULT_RANGING_INTERVAL.as_millis() as u32,
+        }
+    }
+}
+
+impl AnchorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(channel) = self
+            .channels
+            .iter()
+            .find(|channel| ChannelNumber::from_u8(**channel).is_none())
+        {
+            return Err(format!("unsupported channel {}", channel));
+        }
+        if self.ranging_interval == 0 {
+            return Err("null ranging interval".to_string());
+        }
+        // TODO: support extended address
+        match MacAddressMode::from_u8(self.mac_address_mode) {
+            Some(MacAddressMode::AddressMode0) => Ok(()),
+            _ => Err(format!(
+                "unsupported MAC address mode {}",
+                self.mac_address_mode
+            )),
+        }
+    }
+
+    /// Return true if the anchor responds to the controller session.
+    pub fn accepts(&self, session: &Session) -> bool {
+        self.powered
+            && session.get_device_role() != Some(self.role)
+            && self
+                .channels
+                .contains(&(session.get_channel_number() as u8))
+            && (self.session_ids.is_empty() || self.session_ids.contains(&session.get_id()))
+            && (self.sts_keys.is_empty()
+                || session
+                    .get_static_sts_key()
+                    .map_or(false, |key| self.sts_keys.contains(&key)))
+            && session.get_mac_address_mode() as u8 == self.mac_address_mode
+    }
+
+    /// Return true if the anchor runs its own ranging schedule.
+    pub fn initiates(&self) -> bool {
+        self.powered && self.role == DeviceRole::Initiator
+    }
+}
+
+pub struct Anchor {
+    pub mac_address: MacAddress,
+    pub position: Position,
+    config: AnchorConfig,
+    ranging_task: Option<JoinHandle<()>>,
+    pica_tx: mpsc::Sender<PicaCommand>,
+    clock: Clock,
+}
+
+impl Anchor {
+    pub fn new(
+        mac_address: MacAddress,
+        position: Position,
+        config: AnchorConfig,
+        pica_tx: mpsc::Sender<PicaCommand>,
+        clock: Clock,
+    ) -> Self {
+        let mut anchor = Anchor {
+            mac_address,
+            position,
+            config: AnchorConfig::default(),
+            ranging_task: None,
+            pica_tx,
+            clock,
+        };
+        anchor.set_config(config);
+        anchor
+    }
+
+    pub fn config(&self) -> &AnchorConfig {
+        &self.config
+    }
+
+    /// Replace the configuration, and restart the ranging
+    /// schedule of initiator anchors.
+    pub fn set_config(&mut self, config: AnchorConfig) {
+        self.stop_ranging_task();
+        self.config = config;
+        if self.config.initiates() {
+            self.start_ranging_task();
+        }
+    }
+
+    /// Return true if the anchor ranges with the controlee session.
+    /// Controlee sessions list the address of their controller
+    /// as destination address.
+    pub fn controls(&self, session: &Session) -> bool {
+        self.config.initiates()
+            && session.get_device_type() == Some(DeviceType::Controlee)
+            && session.get_active_ranging_interval().is_some()
+            && session.get_dst_mac_addresses().contains(&self.mac_address)
+            && self.config.accepts(session)
+    }
+
+    /// Ranging interval of the anchor, if it initiates ranging.
+    pub fn get_active_ranging_interval(&self) -> Option<Duration> {
+        self.ranging_task
+            .as_ref()
+            .map(|_| Duration::from_millis(self.config.ranging_interval as u64))
+    }
+
+    fn start_ranging_task(&mut self) {
+        let mac_address = self.mac_address;
+        let ranging_interval = Duration::from_millis(self.config.ranging_interval as u64);
+        let tx = self.pica_tx.clone();
+        let clock = self.clock.clone();
+        let mut deadline = self.clock.now();
+        self.ranging_task = Some(tokio::spawn(async move {
+            loop {
+                deadline += ranging_interval;
+                clock.sleep_until(deadline).await;
+                tx.send(PicaCommand::AnchorRanging(mac_address))
+                    .await
+                    .unwrap();
+            }
+        }));
+    }
+
+    fn stop_ranging_task(&mut self) {
+        if let Some(handle) = &self.ranging_task {
+            handle.abort();
+            self.ranging_task = None;
+        }
+    }
+}
+
+impl Drop for Anchor {
+    fn drop(&mut self) {
+        self.stop_ranging_task();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::SessionSnapshot;
+    use crate::Clock;
+    use std::collections::BTreeMap;
+    use tokio::sync::mpsc;
+
+    fn session(app_config: &[(u8, &[u8])]) -> Session {
+        let (tx, _) = mpsc::channel(1);
+        let (pica_tx, _) = mpsc::channel(1);
+        let snapshot = SessionSnapshot {
+            id: 1234,
+            session_type: 0,
+            state: 0,
+            sequence_number: 0,
+            app_config: app_config
+                .iter()
+                .map(|(id, value)| (*id, value.to_vec()))
+                .collect::<BTreeMap<_, _>>(),
+        };
+        Session::from_snapshot(&snapshot, 0, tx, pica_tx, Clock::new())
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn accepts() {
+        let default = session(&[]);
+        assert!(AnchorConfig::default().accepts(&default));
+
+        let config = AnchorConfig {
+            channels: vec![5],
+            ..Default::default()
+        };
+        assert!(!config.accepts(&default));
+        assert!(config.accepts(&session(&[(0x4, &[5])])));
+
+        let config = AnchorConfig {
+            role: DeviceRole::Initiator,
+            ..Default::default()
+        };
+        assert!(config.accepts(&session(&[(0x11, &[0])])));
+        assert!(!config.accepts(&session(&[(0x11, &[1])])));
+
+        let config = AnchorConfig {
+            session_ids: vec![1],
+            ..Default::default()
+        };
+        assert!(!config.accepts(&default));
+
+        let config = AnchorConfig {
+            sts_keys: vec![StsKey::try_from("0708010203040506".to_string()).unwrap()],
+            ..Default::default()
+        };
+        assert!(!config.accepts(&default));
+        assert!(config.accepts(&session(&[(0x27, &[7, 8]), (0x28, &[1, 2, 3, 4, 5, 6])])));
+        assert!(!config.accepts(&session(&[
+            (0x2, &[1]),
+            (0x27, &[7, 8]),
+            (0x28, &[1, 2, 3, 4, 5, 6])
+        ])));
+
+        let config = AnchorConfig {
+            powered: false,
+            ..Default::default()
+        };
+        assert!(!config.accepts(&default));
+        assert!(!AnchorConfig::default().accepts(&session(&[(0x26, &[2])])));
+    }
+
+    #[tokio::test]
+    async fn initiator() {
+        let clock = Clock::new();
+        clock.pause();
+        let (pica_tx, mut pica_rx) = mpsc::channel(1);
+        let mac_address = MacAddress::Short([0, 5]);
+        let anchor = Anchor::new(
+            mac_address,
+            Position::default(),
+            AnchorConfig {
+                role: DeviceRole::Initiator,
+                ranging_interval: 100,
+                ..Default::default()
+            },
+            pica_tx,
+            clock.clone(),
+        );
+        assert_eq!(
+            anchor.get_active_ranging_interval(),
+            Some(Duration::from_millis(100))
+        );
+
+        clock.advance(Duration::from_millis(100));
+        assert!(matches!(
+            pica_rx.recv().await,
+            Some(PicaCommand::AnchorRanging(mac)) if mac == mac_address
+        ));
+
+        let mut controlee = session(&[(0x0, &[0]), (0x5, &[1]), (0x7, &[0, 5])]);
+        assert!(!anchor.controls(&controlee));
+        controlee.restore_state(crate::uci_packets::SessionState::SessionStateActive);
+        assert!(anchor.controls(&controlee));
+        let controller = session(&[(0x0, &[1]), (0x5, &[1]), (0x7, &[0, 5])]);
+        assert!(!anchor.controls(&controller));
+    }
+
+    #[test]
+    fn validate() {
+        assert!(AnchorConfig::default().validate().is_ok());
+        let config = AnchorConfig {
+            channels: vec![7],
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = AnchorConfig {
+            mac_address_mode: 1,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = AnchorConfig {
+            mac_address_mode: 2,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::measurement::{Aoa, MAX_FOM};
+use serde::{Deserialize, Serialize};
+
+/// Angle of arrival characteristics of the antenna array of a device.
+/// The default pattern is ideal: the angle of arrival is valid and
+/// unambiguous in all directions.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AntennaPattern {
+    /// Half angle of the field of view cone around the boresight
+    /// of the device, in degrees. Angles of arrival outside of the
+    /// field of view are reported as 0 with a figure of merit of 0.
+    pub field_of_view: f32,
+    /// When set, the antenna array cannot distinguish the front and
+    /// the back of the device: devices located behind are reported
+    /// at the mirrored position in front.
+    pub front_back_ambiguity: bool,
+    /// Figure of merit at the edge of the field of view. The figure of
+    /// merit decreases linearly from 100 at boresight to this value.
+    pub edge_fom: u8,
+}
+
+impl Default for AntennaPattern {
+    fn default() -> Self {
+        AntennaPattern {
+            field_of_view: 180.,
+            front_back_ambiguity: false,
+            edge_fom: MAX_FOM,
+        }
+    }
+}
+
+impl AntennaPattern {
+    /// Apply the antenna pattern to an angle of arrival measured
+    /// by the device.
+    pub fn apply(&self, aoa: Aoa) -> Aoa {
+        let azimuth = if self.front_back_ambiguity && aoa.azimuth.abs() > 90 {
+            aoa.azimuth.signum() * (180 - aoa.azimuth.abs())
+        } else {
+            aoa.azimuth
+        };
+
+        // Angle between the boresight and the direction of arrival.
+        let (azimuth_rad, elevation_rad) = (
+            (azimuth as f32).to_radians(),
+            (aoa.elevation as f32).to_radians(),
+        );
+        let angle = (azimuth_rad.cos() * elevation_rad.cos())
+            .clamp(-1., 1.)
+            .acos()
+            .to_degrees();
+
+        if angle > self.field_of_view {
+            return Aoa {
+                azimuth: 0,
+                azimuth_fom: 0,
+                elevation: 0,
+                elevation_fom: 0,
+            };
+        }
+
+        let edge_fom = self.edge_fom.min(MAX_FOM) as f32;
+        let fom = if self.field_of_view > 0. {
+            MAX_FOM as f32 - (MAX_FOM as f32 - edge_fom) * angle / self.field_of_view
+        } else {
+            MAX_FOM as f32
+        };
+        let fom = fom.round() as u8;
+
+        Aoa {
+            azimuth,
+            azimuth_fom: aoa.azimuth_fom.min(fom),
+            elevation: aoa.elevation,
+            elevation_fom: aoa.elevation_fom.min(fom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aoa(azimuth: i16, elevation: i8) -> Aoa {
+        Aoa {
+            azimuth,
+            azimuth_fom: MAX_FOM,
+            elevation,
+            elevation_fom: MAX_FOM,
+        }
+    }
+
+    #[test]
+    fn ideal() {
+        let pattern = AntennaPattern::default();
+        let result = pattern.apply(aoa(135, -20));
+        assert_eq!(result.azimuth, 135);
+        assert_eq!(result.elevation, -20);
+        assert_eq!(result.azimuth_fom, MAX_FOM);
+    }
+
+    #[test]
+    fn field_of_view() {
+        let pattern = AntennaPattern {
+            field_of_view: 60.,
+            edge_fom: 40,
+            ..Default::default()
+        };
+        let boresight = pattern.apply(aoa(0, 0));
+        assert_eq!(boresight.azimuth_fom, MAX_FOM);
+        let halfway = pattern.apply(aoa(30, 0));
+        assert_eq!(halfway.azimuth, 30);
+        assert_eq!(halfway.azimuth_fom, 70);
+        let outside = pattern.apply(aoa(50, 45));
+        assert_eq!(outside.azimuth_fom, 0);
+        assert_eq!(outside.elevation_fom, 0);
+    }
+
+    #[test]
+    fn front_back_ambiguity() {
+        let pattern = AntennaPattern {
+            front_back_ambiguity: true,
+            ..Default::default()
+        };
+        assert_eq!(pattern.apply(aoa(150, 0)).azimuth, 30);
+        assert_eq!(pattern.apply(aoa(-120, 0)).azimuth, -60);
+        assert_eq!(pattern.apply(aoa(80, 0)).azimuth, 80);
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Configuration file of the server. Command line options
+//! take precedence over the configuration file.
+
+use pica::{DeviceProfile, MacAddressRange};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Transport of a UCI listener.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+    #[cfg(target_os = "linux")]
+    Pty(PathBuf),
+}
+
+/// Unix domain sockets are only available on Unix platforms.
+#[cfg(unix)]
+fn unix_transport(path: &Path) -> Result<Transport, String> {
+    Ok(Transport::Unix(path.to_path_buf()))
+}
+
+#[cfg(not(unix))]
+fn unix_transport(path: &Path) -> Result<Transport, String> {
+    Err(format!(
+        "Unix domain socket {} is not supported on this platform",
+        path.display()
+    ))
+}
+
+/// Pseudo-terminals are only available on Linux.
+#[cfg(target_os = "linux")]
+fn pty_transport(path: &Path) -> Result<Transport, String> {
+    Ok(Transport::Pty(path.to_path_buf()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pty_transport(path: &Path) -> Result<Transport, String> {
+    Err(format!(
+        "pseudo-terminal {} is not supported on this platform",
+        path.display()
+    ))
+}
+
+/// UCI listener connecting the hosts to devices of the selected
+/// profile. Exactly one of `port`, `unix_socket` and `pty` is set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenerConfig {
+    /// TCP listening address, defaults to the loopback address.
+    pub address: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub unix_socket: Option<PathBuf>,
+    pub pty: Option<PathBuf>,
+    /// Name of the device profile. Devices use the default
+    /// profile when unset.
+    pub profile: Option<String>,
+    /// Give back the MAC address and position of the last device
+    /// connected through the listener, or through any listener of the
+    /// same profile, to the next connected device.
+    pub persistent: bool,
+}
+
+/// Resolved UCI listener.
+#[derive(Debug, Clone)]
+pub struct Listener {
+    pub transport: Transport,
+    pub profile: DeviceProfile,
+    /// Persistent identity of the connected devices.
+    pub identity: Option<String>,
+}
+
+/// Reconnection of the UCI devices with a persistent identity.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReconnectConfig {
+    /// Duration in seconds during which the devices stay in the scene
+    /// after their host disconnected.
+    pub grace_period: f32,
+    /// Resume the sessions of the reconnected devices, the devices
+    /// are reset otherwise.
+    pub resume_sessions: bool,
+}
+
+impl ReconnectConfig {
+    pub fn grace_period(&self) -> Result<Duration, String> {
+        Duration::try_from_secs_f32(self.grace_period)
+            .map_err(|_| format!("invalid grace period {}", self.grace_period))
+    }
+}
+
+/// Listening addresses of a server.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenConfig {
+    /// IPv4 or IPv6 addresses, the server listens on
+    /// all of them.
+    pub addresses: Option<Vec<IpAddr>>,
+    pub port: Option<u16>,
+}
+
+impl ListenConfig {
+    /// Return true if the addresses or the port are set.
+    pub fn is_set(&self) -> bool {
+        self.addresses.is_some() || self.port.is_some()
+    }
+
+    /// Select the socket addresses of the server, from the command line
+    /// options, the configuration file, or the defaults, in this order.
+    pub fn socket_addresses(
+        &self,
+        addresses: &[IpAddr],
+        port: Option<u16>,
+        default_address: IpAddr,
+        default_port: u16,
+    ) -> Vec<SocketAddr> {
+        let port = port.or(self.port).unwrap_or(default_port);
+        let addresses = match (addresses, &self.addresses) {
+            ([], Some(addresses)) => addresses.clone(),
+            ([], None) => vec![default_address],
+            (addresses, _) => addresses.to_vec(),
+        };
+        addresses
+            .into_iter()
+            .map(|address| SocketAddr::new(address, port))
+            .collect()
+    }
+}
+
+/// Listening addresses and sockets of the UCI server.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UciConfig {
+    pub addresses: Option<Vec<IpAddr>>,
+    pub port: Option<u16>,
+    /// Paths of the Unix domain sockets.
+    pub unix_sockets: Option<Vec<PathBuf>>,
+    /// Paths linked to the pseudo-terminals of serial UCI devices.
+    pub ptys: Option<Vec<PathBuf>>,
+    /// Listeners with device profiles, in addition to the
+    /// listeners above.
+    pub listeners: Vec<ListenerConfig>,
+    /// Range of the MAC addresses assigned to the UCI devices
+    /// without fixed address. Anchors cannot use the reserved
+    /// addresses.
+    pub mac_range: Option<MacAddressRange>,
+    pub reconnect: ReconnectConfig,
+}
+
+impl UciConfig {
+    pub fn tcp(&self) -> ListenConfig {
+        ListenConfig {
+            addresses: self.addresses.clone(),
+            port: self.port,
+        }
+    }
+
+    /// Select the paths of the Unix domain sockets, from the command
+    /// line options or the configuration file.
+    pub fn unix_sockets(&self, unix_sockets: &[PathBuf]) -> Result<Vec<Transport>, String> {
+        match (unix_sockets, &self.unix_sockets) {
+            ([], Some(unix_sockets)) => unix_sockets,
+            (unix_sockets, _) => unix_sockets,
+        }
+        .iter()
+        .map(|path| unix_transport(path))
+        .collect()
+    }
+
+    /// Select the paths of the pseudo-terminals, from the command
+    /// line options or the configuration file.
+    pub fn ptys(&self, ptys: &[PathBuf]) -> Result<Vec<Transport>, String> {
+        match (ptys, &self.ptys) {
+            ([], Some(ptys)) => ptys,
+            (ptys, _) => ptys,
+        }
+        .iter()
+        .map(|path| pty_transport(path))
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Listening addresses of the UCI server.
+    pub uci: UciConfig,
+    /// Listening addresses of the web server.
+    pub web: ListenConfig,
+    /// Device profiles indexed by name.
+    pub profiles: BTreeMap<String, DeviceProfile>,
+}
+
+impl Config {
+    /// Parse a configuration from JSON or YAML content.
+    pub fn parse(content: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(content)
+    }
+
+    /// Resolve the transports and profiles of the configured listeners.
+    /// Listeners without a named profile use the default profile.
+    pub fn listeners(
+        &self,
+        default_address: IpAddr,
+        default_profile: &DeviceProfile,
+    ) -> Result<Vec<Listener>, String> {
+        for (name, profile) in &self.profiles {
+            profile
+                .validate()
+                .map_err(|err| format!("invalid profile {}: {}", name, err))?;
+        }
+        self.uci
+            .listeners
+            .iter()
+            .enumerate()
+            .map(|(index, listener)| {
+                let transport = match listener {
+                    ListenerConfig {
+                        address,
+                        port: Some(port),
+                        unix_socket: None,
+                        pty: None,
+                        ..
+                    } => Transport::Tcp(SocketAddr::new(address.unwrap_or(default_address), *port)),
+                    ListenerConfig {
+                        address: None,
+                        port: None,
+                        unix_socket: Some(path),
+                        pty: None,
+                        ..
+                    } => unix_transport(path)?,
+                    ListenerConfig {
+                        address: None,
+                        port: None,
+                        unix_socket: None,
+                        pty: Some(path),
+                        ..
+                    } => pty_transport(path)?,
+                    _ => {
+                        return Err(format!(
+                            "listener {} shall set one of port, unix_socket or pty",
+                            index
+                        ))
+                    }
+                };
+                let profile = match &listener.profile {
+                    Some(name) => self
+                        .profiles
+                        .get(name)
+                        .cloned()
+                        .ok_or(format!("unknown profile {}", name))?,
+                    None => default_profile.clone(),
+                };
+                let identity = match (&listener.profile, &transport) {
+                    _ if !listener.persistent => None,
+                    (Some(name), _) => Some(format!("profile:{}", name)),
+                    (None, Transport::Tcp(address)) => Some(format!("tcp:{}", address)),
+                    #[cfg(unix)]
+                    (None, Transport::Unix(path)) => Some(format!("unix:{}", path.display())),
+                    #[cfg(target_os = "linux")]
+                    (None, Transport::Pty(path)) => Some(format!("pty:{}", path.display())),
+                };
+                Ok(Listener {
+                    transport,
+                    profile,
+                    identity,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn socket_addresses() {
+        let config = Config::parse(
+            r#"
+uci:
+  addresses: ["::1", "127.0.0.1"]
+  port: 7100
+"#,
+        )
+        .unwrap();
+        let default = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        assert_eq!(
+            config.uci.tcp().socket_addresses(&[], None, default, 7000),
+            vec![
+                "[::1]:7100".parse::<SocketAddr>().unwrap(),
+                "127.0.0.1:7100".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            config
+                .uci
+                .tcp()
+                .socket_addresses(&["::".parse().unwrap()], Some(7200), default, 7000),
+            vec!["[::]:7200".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(
+            config.web.socket_addresses(&[], None, default, 3000),
+            vec!["0.0.0.0:3000".parse::<SocketAddr>().unwrap()]
+        );
+        assert!(Config::parse("uci: { address: ::1 }").is_err());
+    }
+
+    #[test]
+    fn reconnect() {
+        let config =
+            Config::parse("uci: { reconnect: { grace_period: 2.5, resume_sessions: true } }")
+                .unwrap();
+        assert_eq!(
+            config.uci.reconnect.grace_period(),
+            Ok(Duration::from_millis(2500))
+        );
+        assert!(config.uci.reconnect.resume_sessions);
+        let config = Config::parse("uci: { reconnect: { grace_period: -1 } }").unwrap();
+        assert!(config.uci.reconnect.grace_period().is_err());
+    }
+
+    #[test]
+    fn mac_range() {
+        let config =
+            Config::parse(r#"uci: { mac_range: { first: "FF:00", last: "FF:FF" } }"#).unwrap();
+        assert_eq!(
+            config.uci.mac_range,
+            Some(MacAddressRange {
+                first: pica::MacAddress::Short([0xff, 0]),
+                last: pica::MacAddress::Short([0xff, 0xff]),
+            })
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn listeners() {
+        let config = Config::parse(
+            r#"
+profiles:
+  tag:
+    mac_address: "00:10"
+uci:
+  listeners:
+    - { port: 7001, profile: tag, persistent: true }
+    - { unix_socket: /tmp/phone.sock, persistent: true }
+    - { pty: /tmp/uwb0 }
+"#,
+        )
+        .unwrap();
+        let listeners = config
+            .listeners(IpAddr::V4(Ipv4Addr::LOCALHOST), &DeviceProfile::default())
+            .unwrap();
+        assert_eq!(
+            listeners[0].transport,
+            Transport::Tcp("127.0.0.1:7001".parse().unwrap())
+        );
+        assert_eq!(
+            listeners[0].profile.mac_address,
+            Some(pica::MacAddress::Short([0, 0x10]))
+        );
+        assert_eq!(listeners[0].identity.as_deref(), Some("profile:tag"));
+        assert_eq!(
+            listeners[1].transport,
+            Transport::Unix("/tmp/phone.sock".into())
+        );
+        assert_eq!(listeners[1].profile.mac_address, None);
+        assert_eq!(
+            listeners[1].identity.as_deref(),
+            Some("unix:/tmp/phone.sock")
+        );
+        assert_eq!(listeners[2].identity, None);
+
+        let mut invalid = config.clone();
+        invalid.uci.listeners[0].profile = Some("phone".to_string());
+        assert!(invalid
+            .listeners(IpAddr::V4(Ipv4Addr::LOCALHOST), &DeviceProfile::default())
+            .is_err());
+        let mut invalid = config;
+        invalid.uci.listeners[1].pty = Some("/tmp/uwb1".into());
+        assert!(invalid
+            .listeners(IpAddr::V4(Ipv4Addr::LOCALHOST), &DeviceProfile::default())
+            .is_err());
+    }
+}
@@ -17,32 +17,164 @@ extern crate num_derive;
 extern crate num_traits;
 extern crate thiserror;
 
+mod config;
+#[cfg(target_os = "linux")]
+mod pty;
 mod web;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
-use pica::{Pica, PicaCommand};
-use std::net::{Ipv4Addr, SocketAddrV4};
+use config::{Config, Listener, Transport};
+use pica::{DeviceProfile, Pica, PicaCommand, Scenario, Scene};
+#[cfg(target_os = "linux")]
+use pty::Pty;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::path::Path;
 use std::path::PathBuf;
+use std::pin::Pin;
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+#[cfg(target_os = "linux")]
+use tokio::sync::oneshot;
 use tokio::sync::{broadcast, mpsc};
 use tokio::try_join;
 
+const DEFAULT_UCI_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
 const DEFAULT_UCI_PORT: u16 = 7000;
+const DEFAULT_WEB_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
 const DEFAULT_WEB_PORT: u16 = 3000;
 
-async fn accept_incoming(tx: mpsc::Sender<PicaCommand>, uci_port: u16) -> Result<()> {
-    let uci_socket = SocketAddrV4::new(Ipv4Addr::LOCALHOST, uci_port);
-    let uci_listener = TcpListener::bind(uci_socket).await?;
-    println!("Pica: Listening on: {}", uci_port);
+type Server = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
 
+async fn accept_incoming(
+    tx: mpsc::Sender<PicaCommand>,
+    uci_listener: TcpListener,
+    profile: DeviceProfile,
+    identity: Option<String>,
+) -> Result<()> {
     loop {
         let (socket, addr) = uci_listener.accept().await?;
         println!("Uwb host addr: {}", addr);
-        tx.send(PicaCommand::Connect(socket)).await?
+        tx.send(PicaCommand::Connect(
+            Box::new(socket),
+            profile.clone(),
+            identity.clone(),
+        ))
+        .await?
     }
 }
 
+#[cfg(unix)]
+async fn accept_incoming_unix(
+    tx: mpsc::Sender<PicaCommand>,
+    uci_listener: UnixListener,
+    profile: DeviceProfile,
+    identity: Option<String>,
+) -> Result<()> {
+    loop {
+        let (socket, _) = uci_listener.accept().await?;
+        println!("Uwb host connected on Unix domain socket");
+        tx.send(PicaCommand::Connect(
+            Box::new(socket),
+            profile.clone(),
+            identity.clone(),
+        ))
+        .await?
+    }
+}
+
+/// Bind a Unix domain socket, replacing the socket
+/// left over by a previous instance.
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> Result<UnixListener> {
+    if std::fs::symlink_metadata(path).map_or(false, |metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    Ok(UnixListener::bind(path)?)
+}
+
+/// Expose a UCI device as a serial device. A new pseudo-terminal
+/// is linked to the same path each time the device is disconnected.
+#[cfg(target_os = "linux")]
+async fn connect_pty(
+    tx: mpsc::Sender<PicaCommand>,
+    path: PathBuf,
+    (mut pty, mut closed): (Pty, oneshot::Receiver<()>),
+    profile: DeviceProfile,
+    identity: Option<String>,
+) -> Result<()> {
+    loop {
+        tx.send(PicaCommand::Connect(
+            Box::new(pty),
+            profile.clone(),
+            identity.clone(),
+        ))
+        .await?;
+        // The sender is dropped with the pseudo-terminal.
+        let _ = closed.await;
+        // The connections are dropped after Pica when the simulation stops.
+        if tx.is_closed() {
+            return Ok(());
+        }
+        (pty, closed) = Pty::open(&path)
+            .with_context(|| format!("Failed to open pseudo-terminal {}", path.display()))?;
+        println!("Pica: Serial device reopened: {}", path.display());
+    }
+}
+
+/// Bind the listener, and return the server task.
+async fn listen(tx: mpsc::Sender<PicaCommand>, listener: Listener) -> Result<Server> {
+    let Listener {
+        transport,
+        profile,
+        identity,
+    } = listener;
+    Ok(match transport {
+        Transport::Tcp(address) => {
+            let uci_listener = TcpListener::bind(address)
+                .await
+                .with_context(|| format!("Failed to bind UCI server to {}", address))?;
+            println!("Pica: Listening on: {}", uci_listener.local_addr()?);
+            Box::pin(accept_incoming(tx, uci_listener, profile, identity))
+        }
+        #[cfg(unix)]
+        Transport::Unix(path) => {
+            let uci_listener = bind_unix(&path)
+                .with_context(|| format!("Failed to bind UCI server to {}", path.display()))?;
+            println!("Pica: Listening on: {}", path.display());
+            Box::pin(accept_incoming_unix(tx, uci_listener, profile, identity))
+        }
+        #[cfg(target_os = "linux")]
+        Transport::Pty(path) => {
+            let pty = Pty::open(&path)
+                .with_context(|| format!("Failed to open pseudo-terminal {}", path.display()))?;
+            println!("Pica: Serial device: {}", path.display());
+            Box::pin(connect_pty(tx, path, pty, profile, identity))
+        }
+    })
+}
+
+/// Run the servers concurrently, until one of them fails.
+async fn run_servers(servers: Vec<Server>) -> Result<()> {
+    let (result_tx, mut result_rx) = mpsc::channel(1);
+    for server in servers {
+        let result_tx = result_tx.clone();
+        tokio::spawn(async move {
+            let _ = result_tx.send(server.await).await;
+        });
+    }
+    drop(result_tx);
+    while let Some(result) = result_rx.recv().await {
+        result?
+    }
+    Ok(())
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "pica", about = "Virtual UWB subsystem")]
 struct Args {
@@ -51,31 +183,219 @@ struct Args {
     /// saved under the name `device-{handle}.pcapng`.
     #[arg(short, long, value_name = "PCAPNG_DIR")]
     pcapng_dir: Option<PathBuf>,
-    /// Configure the TCP port for the UCI server.
-    #[arg(short, long, value_name = "UCI_PORT", default_value_t = DEFAULT_UCI_PORT)]
-    uci_port: u16,
-    /// Configure the HTTP port for the web interface.
-    #[arg(short, long, value_name = "WEB_PORT", default_value_t = DEFAULT_WEB_PORT)]
-    web_port: u16,
+    /// Configuration file, in JSON or YAML format. Command line
+    /// options take precedence over the configuration file.
+    #[arg(short, long, value_name = "CONFIG")]
+    config: Option<PathBuf>,
+    /// Configure the TCP port for the UCI server [default: 7000].
+    #[arg(short, long, value_name = "UCI_PORT")]
+    uci_port: Option<u16>,
+    /// Configure the IPv4 or IPv6 listening address of the UCI server,
+    /// can be repeated to listen on several addresses [default: 127.0.0.1].
+    #[arg(long, value_name = "UCI_ADDRESS")]
+    uci_address: Vec<IpAddr>,
+    /// Configure the path of a Unix domain socket for the UCI server,
+    /// can be repeated. The TCP server is disabled when only Unix
+    /// domain sockets or pseudo-terminals are configured.
+    #[cfg_attr(unix, arg(long, value_name = "UCI_UNIX_SOCKET"))]
+    #[cfg_attr(not(unix), arg(skip))]
+    uci_unix_socket: Vec<PathBuf>,
+    /// Expose a UCI device as a pseudo-terminal linked to the selected
+    /// path, can be repeated. The UWB host opens the path as a serial
+    /// device, with UCI packets framed over the byte stream.
+    #[cfg_attr(target_os = "linux", arg(long, value_name = "UCI_PTY"))]
+    #[cfg_attr(not(target_os = "linux"), arg(skip))]
+    uci_pty: Vec<PathBuf>,
+    /// Device profile file, in JSON or YAML format, selecting the
+    /// capabilities and device information of the UCI devices
+    /// connected through listeners without a named profile.
+    #[arg(long, value_name = "PROFILE")]
+    profile: Option<PathBuf>,
+    /// Configure the HTTP port for the web interface [default: 3000].
+    #[arg(short, long, value_name = "WEB_PORT")]
+    web_port: Option<u16>,
+    /// Configure the IPv4 or IPv6 listening address of the web interface,
+    /// can be repeated to listen on several addresses [default: 0.0.0.0].
+    #[arg(long, value_name = "WEB_ADDRESS")]
+    web_address: Vec<IpAddr>,
+    /// Scene file loaded at startup, in JSON or YAML format.
+    /// The scene describes the anchors, the positions of the UCI
+    /// devices, the obstacles and the trajectories.
+    #[arg(short, long, value_name = "SCENE")]
+    scene: Option<PathBuf>,
+    /// Scenario file executed at startup, in JSON or YAML format.
+    /// Pica exits when the scenario completes, with an error
+    /// if one of the steps failed.
+    #[arg(long, value_name = "SCENARIO")]
+    scenario: Option<PathBuf>,
+    /// Number of simulated seconds per real second. Rates above 1
+    /// run the simulation faster than real time.
+    #[arg(long, value_name = "RATE", default_value_t = 1., value_parser = parse_clock_rate)]
+    clock_rate: f64,
+}
+
+fn parse_clock_rate(rate: &str) -> Result<f64, String> {
+    match rate.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0. => Ok(rate),
+        _ => Err(format!("invalid clock rate {}", rate)),
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    assert_ne!(
-        args.uci_port, args.web_port,
-        "UCI port and Web port shall be different."
+    let config = match &args.config {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config {}", path.display()))?;
+            Config::parse(&content)
+                .with_context(|| format!("Failed to parse config {}", path.display()))?
+        }
+        None => Config::default(),
+    };
+    let default_profile = match &args.profile {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read profile {}", path.display()))?;
+            let profile = DeviceProfile::parse(&content)
+                .with_context(|| format!("Failed to parse profile {}", path.display()))?;
+            profile
+                .validate()
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("Invalid profile {}", path.display()))?;
+            profile
+        }
+        None => DeviceProfile::default(),
+    };
+    let uci_unix_sockets = config
+        .uci
+        .unix_sockets(&args.uci_unix_socket)
+        .map_err(anyhow::Error::msg)
+        .context("Invalid config")?;
+    let uci_ptys = config
+        .uci
+        .ptys(&args.uci_pty)
+        .map_err(anyhow::Error::msg)
+        .context("Invalid config")?;
+    let uci_tcp = config.uci.tcp();
+    let uci_listeners = config
+        .listeners(DEFAULT_UCI_ADDRESS, &default_profile)
+        .map_err(anyhow::Error::msg)
+        .context("Invalid config")?;
+    let uci_addresses =
+        if (uci_unix_sockets.is_empty() && uci_ptys.is_empty() && uci_listeners.is_empty())
+            || uci_tcp.is_set()
+            || !args.uci_address.is_empty()
+            || args.uci_port.is_some()
+        {
+            uci_tcp.socket_addresses(
+                &args.uci_address,
+                args.uci_port,
+                DEFAULT_UCI_ADDRESS,
+                DEFAULT_UCI_PORT,
+            )
+        } else {
+            vec![]
+        };
+    let web_addresses = config.web.socket_addresses(
+        &args.web_address,
+        args.web_port,
+        DEFAULT_WEB_ADDRESS,
+        DEFAULT_WEB_PORT,
     );
     let (event_tx, _) = broadcast::channel(16);
 
     let mut pica = Pica::new(event_tx.clone(), args.pcapng_dir);
     let pica_tx = pica.tx();
+    let clock = pica.clock();
+    clock
+        .set_rate(args.clock_rate)
+        .map_err(anyhow::Error::msg)
+        .context("Invalid clock rate")?;
+
+    if let Some(range) = config.uci.mac_range {
+        pica.set_uci_mac_range(range)
+            .map_err(anyhow::Error::msg)
+            .context("Invalid UCI MAC address range")?;
+    }
+
+    let grace_period = config
+        .uci
+        .reconnect
+        .grace_period()
+        .map_err(anyhow::Error::msg)
+        .context("Invalid reconnect config")?;
+    pica.set_reconnect_grace_period(grace_period, config.uci.reconnect.resume_sessions);
 
-    try_join!(
-        accept_incoming(pica_tx.clone(), args.uci_port),
-        pica.run(),
-        web::serve(pica_tx, event_tx, args.web_port)
-    )?;
+    if let Some(path) = args.scene {
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read scene {}", path.display()))?;
+        let scene = Scene::parse(&content)
+            .with_context(|| format!("Failed to parse scene {}", path.display()))?;
+        pica.load_scene(scene)
+            .with_context(|| format!("Failed to load scene {}", path.display()))?;
+    }
+
+    let scenario = match args.scenario {
+        Some(path) => {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read scenario {}", path.display()))?;
+            Some(
+                Scenario::parse(&content)
+                    .with_context(|| format!("Failed to parse scenario {}", path.display()))?,
+            )
+        }
+        None => None,
+    };
+    let events = event_tx.subscribe();
+    let (scenario_clock, scenario_tx) = (clock.clone(), pica_tx.clone());
+
+    let transports = uci_addresses
+        .into_iter()
+        .map(Transport::Tcp)
+        .chain(uci_unix_sockets)
+        .chain(uci_ptys)
+        .map(|transport| Listener {
+            transport,
+            profile: default_profile.clone(),
+            identity: None,
+        })
+        .chain(uci_listeners);
+    let mut servers: Vec<Server> = Vec::new();
+    for listener in transports {
+        servers.push(listen(pica_tx.clone(), listener).await?);
+    }
+    for address in web_addresses {
+        servers.push(Box::pin(web::serve(
+            pica_tx.clone(),
+            event_tx.clone(),
+            clock.clone(),
+            address,
+        )));
+    }
+
+    tokio::select! {
+        result = async {
+            try_join!(run_servers(servers), pica.run())
+        } => {
+            result?;
+        }
+        Some(result) = async move {
+            match scenario {
+                Some(scenario) => Some(scenario.run(scenario_clock, scenario_tx, events).await),
+                None => None,
+            }
+        } => {
+            result.context("Scenario failed")?;
+            println!("Pica: Scenario completed");
+        }
+        // Return on interrupt, so that the serial device links
+        // are removed when the connections are dropped.
+        result = tokio::signal::ctrl_c() => {
+            result?;
+            println!("Pica: Interrupted");
+        }
+    }
 
     Ok(())
 }
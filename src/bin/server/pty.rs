@@ -0,0 +1,141 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pseudo-terminal exposing a UCI device as a serial port.
+
+use anyhow::{Context, Result};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::pty::{grantpt, posix_openpt, ptsname_r, unlockpt, PtyMaster};
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{ready, Context as TaskContext, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::oneshot;
+
+/// Master side of a pseudo-terminal. The UWB host opens the slave
+/// side as a serial device.
+#[derive(Debug)]
+pub struct Pty {
+    master: AsyncFd<PtyMaster>,
+    /// The slave side is kept open, so that the master side is not
+    /// hung up when the host closes the serial device.
+    _slave: File,
+    slave_path: String,
+    link: PathBuf,
+    /// Dropped with the pseudo-terminal, when the device is disconnected.
+    _closed: oneshot::Sender<()>,
+}
+
+impl Pty {
+    /// Open a pseudo-terminal in raw mode, and link the slave
+    /// device to the selected path. The returned receiver completes
+    /// when the pseudo-terminal is closed.
+    pub fn open(link: &Path) -> Result<(Self, oneshot::Receiver<()>)> {
+        let master = posix_openpt(OFlag::O_RDWR | OFlag::O_NOCTTY)?;
+        grantpt(&master)?;
+        unlockpt(&master)?;
+        let slave_path = ptsname_r(&master)?;
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(OFlag::O_NOCTTY.bits())
+            .open(&slave_path)
+            .with_context(|| format!("Failed to open {}", slave_path))?;
+
+        // Disable echo and line processing, the UCI packets
+        // are transferred unmodified.
+        let mut termios = tcgetattr(slave.as_raw_fd())?;
+        cfmakeraw(&mut termios);
+        tcsetattr(slave.as_raw_fd(), SetArg::TCSANOW, &termios)?;
+
+        fcntl(master.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+
+        if std::fs::symlink_metadata(link)
+            .map_or(false, |metadata| metadata.file_type().is_symlink())
+        {
+            std::fs::remove_file(link)?;
+        }
+        std::os::unix::fs::symlink(&slave_path, link)
+            .with_context(|| format!("Failed to link {} to {}", link.display(), slave_path))?;
+
+        let (closed_tx, closed_rx) = oneshot::channel();
+        Ok((
+            Pty {
+                master: AsyncFd::new(master)?,
+                _slave: slave,
+                slave_path,
+                link: link.to_owned(),
+                _closed: closed_tx,
+            },
+            closed_rx,
+        ))
+    }
+}
+
+impl Drop for Pty {
+    /// Remove the link, unless it was replaced in the meantime.
+    fn drop(&mut self) {
+        if std::fs::read_link(&self.link)
+            .map_or(false, |target| target == Path::new(&self.slave_path))
+        {
+            let _ = std::fs::remove_file(&self.link);
+        }
+    }
+}
+
+impl AsyncRead for Pty {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.master.poll_read_ready(cx))?;
+            match guard.try_io(|master| (&mut master.get_ref()).read(buf.initialize_unfilled())) {
+                Ok(result) => return Poll::Ready(result.map(|len| buf.advance(len))),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Pty {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.master.poll_write_ready(cx))?;
+            match guard.try_io(|master| (&mut master.get_ref()).write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -13,20 +13,21 @@
 // limitations under the License.
 
 use std::convert::Infallible;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::SocketAddr;
 
 use anyhow::{Context, Result};
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{body, Body, Request, Response, Server, StatusCode as HttpStatusCode};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use serde_json::error::Category as SerdeErrorCategory;
 use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio_stream::{wrappers::BroadcastStream, StreamExt};
 
 use pica::{
-    Category, MacAddress, PicaCommand, PicaCommandError, PicaCommandStatus, PicaEvent, Position,
+    AnchorConfig, AntennaPattern, Category, Clock, ClockState, DeviceProfile, MacAddress,
+    NoiseModel, Obstacle, PicaCommand, PicaCommandError, PicaCommandStatus, PicaEvent, Position,
+    Scenario, Scene, Snapshot, Trajectory, VirtualDevice, Zone,
 };
-use PicaEvent::{DeviceAdded, DeviceRemoved, DeviceUpdated, NeighborUpdated};
 
 const STATIC_FILES: &[(&str, &str, &str)] = &[
     ("/", "text/html", include_str!("../../../static/index.html")),
@@ -57,23 +58,13 @@ const STATIC_FILES: &[(&str, &str, &str)] = &[
     ),
 ];
 
-#[derive(Deserialize)]
-struct PositionBody {
-    x: i16,
-    y: i16,
-    z: i16,
-    yaw: i16,
-    pitch: i8,
-    roll: i16,
-}
-
 macro_rules! position {
     ($body: ident) => {
         position!($body, false)
     };
     ($body: ident, $mandatory: ident) => {
-        match serde_json::from_slice::<PositionBody>(&$body) {
-            Ok(body) => Position::new(body.x, body.y, body.z, body.yaw, body.pitch, body.roll),
+        match serde_json::from_slice::<Position>(&$body) {
+            Ok(position) => position,
             Err(err) => {
                 if !$mandatory && err.classify() == SerdeErrorCategory::Eof {
                     Position::default()
@@ -87,6 +78,19 @@ macro_rules! position {
     };
 }
 
+macro_rules! json_body {
+    ($body: ident, $type: ty) => {
+        match serde_json::from_slice::<$type>(&$body) {
+            Ok(value) => value,
+            Err(err) => {
+                let reason = format!("Error while deserializing {}: {}", stringify!($type), err);
+                println!("{}", reason);
+                return Ok(Response::builder().status(406).body(reason.into()).unwrap());
+            }
+        }
+    };
+}
+
 macro_rules! mac_address {
     ($mac_address: ident) => {
         match MacAddress::new($mac_address.to_string()) {
@@ -108,12 +112,24 @@ struct Device {
     pub position: Position,
 }
 
-fn event_name(event: &PicaEvent) -> &'static str {
-    match event {
-        DeviceAdded { .. } => "device-added",
-        DeviceRemoved { .. } => "device-removed",
-        DeviceUpdated { .. } => "device-updated",
-        NeighborUpdated { .. } => "neighbor-updated",
+fn error_status(err: &PicaCommandError) -> HttpStatusCode {
+    match err {
+        PicaCommandError::DeviceAlreadyExists(_) => HttpStatusCode::CONFLICT,
+        PicaCommandError::DeviceNotFound(_) => HttpStatusCode::NOT_FOUND,
+        PicaCommandError::ObstacleAlreadyExists(_) => HttpStatusCode::CONFLICT,
+        PicaCommandError::ObstacleNotFound(_) => HttpStatusCode::NOT_FOUND,
+        PicaCommandError::ZoneAlreadyExists(_) => HttpStatusCode::CONFLICT,
+        PicaCommandError::ZoneNotFound(_) => HttpStatusCode::NOT_FOUND,
+        PicaCommandError::InvalidZone(_) => HttpStatusCode::BAD_REQUEST,
+        PicaCommandError::InvalidTrajectory(_) => HttpStatusCode::BAD_REQUEST,
+        PicaCommandError::InvalidSnapshot(_) => HttpStatusCode::BAD_REQUEST,
+        PicaCommandError::InvalidAnchorConfig(_) => HttpStatusCode::BAD_REQUEST,
+        PicaCommandError::InvalidVirtualDevice(_) => HttpStatusCode::BAD_REQUEST,
+        PicaCommandError::InvalidDeviceProfile(_) => HttpStatusCode::BAD_REQUEST,
+        PicaCommandError::ReservedMacAddress(_) => HttpStatusCode::BAD_REQUEST,
+        PicaCommandError::InvalidClockRate(_) => HttpStatusCode::BAD_REQUEST,
+        PicaCommandError::DeviceNotConnected(_) => HttpStatusCode::CONFLICT,
+        PicaCommandError::SessionNotFound(_, _) => HttpStatusCode::NOT_FOUND,
     }
 }
 
@@ -121,6 +137,7 @@ async fn handle(
     mut req: Request<Body>,
     tx: mpsc::Sender<PicaCommand>,
     events: broadcast::Sender<PicaEvent>,
+    clock: Clock,
 ) -> Result<Response<Body>, Infallible> {
     let static_file = STATIC_FILES
         .iter()
@@ -141,13 +158,7 @@ async fn handle(
         tx.send(pica_cmd).await.unwrap();
         let (status, description) = match pica_cmd_rsp_rx.await {
             Ok(Ok(_)) => (HttpStatusCode::OK, "success".into()),
-            Ok(Err(err)) => (
-                match err {
-                    PicaCommandError::DeviceAlreadyExists(_) => HttpStatusCode::CONFLICT,
-                    PicaCommandError::DeviceNotFound(_) => HttpStatusCode::NOT_FOUND,
-                },
-                format!("{}", err),
-            ),
+            Ok(Err(err)) => (error_status(&err), format!("{}", err)),
             Err(err) => (
                 HttpStatusCode::INTERNAL_SERVER_ERROR,
                 format!("Error getting command response: {}", err),
@@ -172,7 +183,7 @@ async fn handle(
                 result.map(|event| {
                     format!(
                         "event: {}\ndata: {}\n\n",
-                        event_name(&event),
+                        event.name(),
                         serde_json::to_string(&event).unwrap()
                     )
                 })
@@ -190,6 +201,27 @@ async fn handle(
             ))
             .await);
         }
+        ["disconnect-uci-device", mac_address] => {
+            return Ok(send_cmd(PicaCommand::DisconnectUciDevice(
+                mac_address!(mac_address),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["reset-uci-device", mac_address] => {
+            return Ok(send_cmd(PicaCommand::ResetUciDevice(
+                mac_address!(mac_address),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["set-uci-device-error", mac_address] => {
+            return Ok(send_cmd(PicaCommand::SetUciDeviceError(
+                mac_address!(mac_address),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
         ["set-position", mac_address] => {
             return Ok(send_cmd(PicaCommand::SetPosition(
                 mac_address!(mac_address),
@@ -213,6 +245,275 @@ async fn handle(
             ))
             .await);
         }
+        ["create-virtual-device"] => {
+            let virtual_device = if body.is_empty() {
+                VirtualDevice::default()
+            } else {
+                json_body!(body, VirtualDevice)
+            };
+            println!("PicaCommand: CreateVirtualDevice");
+            let (rsp_tx, rsp_rx) = oneshot::channel();
+            tx.send(PicaCommand::CreateVirtualDevice(virtual_device, rsp_tx))
+                .await
+                .unwrap();
+            let response = match rsp_rx.await {
+                Ok(Ok(mac_address)) => Response::builder()
+                    .status(HttpStatusCode::OK)
+                    .header("content-type", "application/json")
+                    .body(
+                        serde_json::json!({ "mac_address": mac_address })
+                            .to_string()
+                            .into(),
+                    ),
+                Ok(Err(err)) => Response::builder()
+                    .status(error_status(&err))
+                    .body(format!("{}", err).into()),
+                Err(err) => Response::builder()
+                    .status(HttpStatusCode::INTERNAL_SERVER_ERROR)
+                    .body(format!("Error getting command response: {}", err).into()),
+            };
+            return Ok(response.unwrap());
+        }
+        ["destroy-virtual-device", mac_address] => {
+            return Ok(send_cmd(PicaCommand::DestroyVirtualDevice(
+                mac_address!(mac_address),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["set-anchor-config", mac_address] => {
+            return Ok(send_cmd(PicaCommand::SetAnchorConfig(
+                mac_address!(mac_address),
+                json_body!(body, AnchorConfig),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["set-device-profile", mac_address] => {
+            return Ok(send_cmd(PicaCommand::SetDeviceProfile(
+                mac_address!(mac_address),
+                json_body!(body, DeviceProfile),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["set-noise-model", mac_address] => {
+            return Ok(send_cmd(PicaCommand::SetNoiseModel(
+                mac_address!(mac_address),
+                None,
+                json_body!(body, NoiseModel),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["set-noise-model", mac_address, peer_mac_address] => {
+            return Ok(send_cmd(PicaCommand::SetNoiseModel(
+                mac_address!(mac_address),
+                Some(mac_address!(peer_mac_address)),
+                json_body!(body, NoiseModel),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["set-antenna-pattern", mac_address] => {
+            return Ok(send_cmd(PicaCommand::SetAntennaPattern(
+                mac_address!(mac_address),
+                json_body!(body, AntennaPattern),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["set-trajectory", mac_address] => {
+            return Ok(send_cmd(PicaCommand::SetTrajectory(
+                mac_address!(mac_address),
+                json_body!(body, Trajectory),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["clear-trajectory", mac_address] => {
+            return Ok(send_cmd(PicaCommand::ClearTrajectory(
+                mac_address!(mac_address),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["create-obstacle", name] => {
+            return Ok(send_cmd(PicaCommand::CreateObstacle(
+                name.to_string(),
+                json_body!(body, Obstacle),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["destroy-obstacle", name] => {
+            return Ok(send_cmd(PicaCommand::DestroyObstacle(
+                name.to_string(),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["create-zone", name] => {
+            return Ok(send_cmd(PicaCommand::CreateZone(
+                name.to_string(),
+                json_body!(body, Zone),
+                pica_cmd_rsp_tx,
+            ))
+            .await);
+        }
+        ["destroy-zone", name] => {
+            return Ok(send_cmd(PicaCommand::DestroyZone(name.to_string(), pica_cmd_rsp_tx)).await);
+        }
+        ["import-scene"] => {
+            let scene = match Scene::parse(&String::from_utf8_lossy(&body)) {
+                Ok(scene) => scene,
+                Err(err) => {
+                    let reason = format!("Error while deserializing scene: {}", err);
+                    println!("{}", reason);
+                    return Ok(Response::builder().status(406).body(reason.into()).unwrap());
+                }
+            };
+            return Ok(send_cmd(PicaCommand::LoadScene(scene, pica_cmd_rsp_tx)).await);
+        }
+        ["export-scene"] => {
+            println!("PicaCommand: SaveScene");
+            let (scene_tx, scene_rx) = oneshot::channel::<Scene>();
+            tx.send(PicaCommand::SaveScene(scene_tx)).await.unwrap();
+            let scene = scene_rx.await.unwrap_or_default();
+            let (mime, body) = if req.uri().query() == Some("format=yaml") {
+                ("text/yaml", scene.to_yaml().unwrap())
+            } else {
+                ("application/json", serde_json::to_string(&scene).unwrap())
+            };
+            return Ok(Response::builder()
+                .status(200)
+                .header("content-type", mime)
+                .body(body.into())
+                .unwrap());
+        }
+        ["snapshot"] => {
+            println!("PicaCommand: Snapshot");
+            let (snapshot_tx, snapshot_rx) = oneshot::channel::<Snapshot>();
+            tx.send(PicaCommand::Snapshot(snapshot_tx)).await.unwrap();
+            let snapshot = snapshot_rx.await.unwrap_or_default();
+            return Ok(Response::builder()
+                .status(200)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&snapshot).unwrap().into())
+                .unwrap());
+        }
+        ["restore"] => {
+            return Ok(send_cmd(PicaCommand::Restore(
+                json_body!(body, Snapshot),
+                pica_cmd_rsp_tx,
+            ))
+            .await)
+        }
+        ["run-scenario"] => {
+            let scenario = match Scenario::parse(&String::from_utf8_lossy(&body)) {
+                Ok(scenario) => scenario,
+                Err(err) => {
+                    let reason = format!("Error while deserializing scenario: {}", err);
+                    println!("{}", reason);
+                    return Ok(Response::builder().status(406).body(reason.into()).unwrap());
+                }
+            };
+            println!("Run scenario");
+            let (status, description) =
+                match scenario.run(clock, tx.clone(), events.subscribe()).await {
+                    Ok(()) => (HttpStatusCode::OK, "success".to_string()),
+                    Err(err) => (HttpStatusCode::EXPECTATION_FAILED, format!("{}", err)),
+                };
+            println!("  status: {}, {}", status, description);
+            return Ok(Response::builder()
+                .status(status)
+                .body(description.into())
+                .unwrap());
+        }
+        ["pause"] => return Ok(send_cmd(PicaCommand::PauseClock(pica_cmd_rsp_tx)).await),
+        ["resume"] => return Ok(send_cmd(PicaCommand::ResumeClock(pica_cmd_rsp_tx)).await),
+        ["set-clock-rate", rate] => {
+            let rate = match rate.parse::<f64>() {
+                Ok(rate) if rate.is_finite() && rate > 0. => rate,
+                _ => {
+                    let reason = format!("Error clock rate: {}", rate);
+                    println!("{}", reason);
+                    return Ok(Response::builder().status(406).body(reason.into()).unwrap());
+                }
+            };
+            return Ok(send_cmd(PicaCommand::SetClockRate(rate, pica_cmd_rsp_tx)).await);
+        }
+        ["step", count] => {
+            let count = match count.parse::<u32>() {
+                Ok(count) => count,
+                Err(err) => {
+                    let reason = format!("Error step count: {}", err);
+                    println!("{}", reason);
+                    return Ok(Response::builder().status(406).body(reason.into()).unwrap());
+                }
+            };
+            return Ok(send_cmd(PicaCommand::StepClock(count, pica_cmd_rsp_tx)).await);
+        }
+        ["get-clock"] => {
+            println!("PicaCommand: GetClock");
+            let (clock_tx, clock_rx) = oneshot::channel::<ClockState>();
+            tx.send(PicaCommand::GetClock(clock_tx)).await.unwrap();
+            return Ok(match clock_rx.await {
+                Ok(state) => Response::builder()
+                    .status(200)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&state).unwrap().into())
+                    .unwrap(),
+                Err(_) => Response::builder().status(500).body("".into()).unwrap(),
+            });
+        }
+        ["get-uci-devices"] => {
+            println!("PicaCommand: GetUciDevices");
+            let (devices_tx, devices_rx) = oneshot::channel();
+            tx.send(PicaCommand::GetUciDevices(devices_tx))
+                .await
+                .unwrap();
+            return Ok(match devices_rx.await {
+                Ok(devices) => Response::builder()
+                    .status(200)
+                    .header("content-type", "application/json")
+                    .body(serde_json::json!({ "devices": devices }).to_string().into())
+                    .unwrap(),
+                Err(_) => Response::builder().status(500).body("".into()).unwrap(),
+            });
+        }
+        ["get-session", mac_address, session_id] => {
+            let session_id = match parse_session_id(session_id) {
+                Ok(session_id) => session_id,
+                Err(err) => {
+                    let reason = format!("Error session id: {}", err);
+                    println!("{}", reason);
+                    return Ok(Response::builder().status(406).body(reason.into()).unwrap());
+                }
+            };
+            println!("PicaCommand: GetSession");
+            let (session_tx, session_rx) = oneshot::channel();
+            tx.send(PicaCommand::GetSession(
+                mac_address!(mac_address),
+                session_id,
+                session_tx,
+            ))
+            .await
+            .unwrap();
+            let response = match session_rx.await {
+                Ok(Ok(session)) => Response::builder()
+                    .status(HttpStatusCode::OK)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&session).unwrap().into()),
+                Ok(Err(err)) => Response::builder()
+                    .status(error_status(&err))
+                    .body(format!("{}", err).into()),
+                Err(err) => Response::builder()
+                    .status(HttpStatusCode::INTERNAL_SERVER_ERROR)
+                    .body(format!("Error getting command response: {}", err).into()),
+            };
+            return Ok(response.unwrap());
+        }
         ["get-state"] => {
             #[derive(Serialize)]
             struct GetStateResponse {
@@ -244,26 +545,36 @@ async fn handle(
     Ok(Response::builder().status(404).body("".into()).unwrap())
 }
 
+/// Parse a session id, in decimal or in hexadecimal with the `0x` prefix.
+fn parse_session_id(session_id: &str) -> Result<u32, std::num::ParseIntError> {
+    match session_id.strip_prefix("0x") {
+        Some(session_id) => u32::from_str_radix(session_id, 16),
+        None => session_id.parse(),
+    }
+}
+
 pub async fn serve(
     tx: mpsc::Sender<PicaCommand>,
     events: broadcast::Sender<PicaEvent>,
-    web_port: u16,
+    clock: Clock,
+    addr: SocketAddr,
 ) -> Result<()> {
-    let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, web_port);
-
     let make_svc = make_service_fn(move |_conn| {
         let tx = tx.clone();
         let events = events.clone();
+        let clock = clock.clone();
         async move {
             Ok::<_, Infallible>(service_fn(move |req| {
-                handle(req, tx.clone(), events.clone())
+                handle(req, tx.clone(), events.clone(), clock.clone())
             }))
         }
     });
 
-    let server = Server::bind(&addr.into()).serve(make_svc);
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("Failed to bind web server to {}", addr))?
+        .serve(make_svc);
 
-    println!("Pica: Web server started on http://0.0.0.0:{}", web_port);
+    println!("Pica: Web server started on http://{}", server.local_addr());
 
     server.await.context("Web Server Error")
 }
@@ -0,0 +1,196 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Simulation clock pacing the ranging sessions, trajectories and
+//! scenarios. The clock can be paused, advanced by steps, or run
+//! faster than real time.
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{self, Instant};
+
+struct State {
+    /// Simulation time at the last change of state.
+    origin: Duration,
+    /// Real time at the last change of state.
+    real_origin: Instant,
+    /// Simulation seconds elapsed per real second when running.
+    rate: f64,
+    paused: bool,
+}
+
+impl State {
+    fn now(&self) -> Duration {
+        if self.paused {
+            self.origin
+        } else {
+            self.origin + self.real_origin.elapsed().mul_f64(self.rate)
+        }
+    }
+
+    fn rebase(&mut self) {
+        self.origin = self.now();
+        self.real_origin = Instant::now();
+    }
+}
+
+/// Snapshot of the state of the clock.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ClockState {
+    /// Simulation time, in seconds.
+    pub time: f64,
+    pub rate: f64,
+    pub paused: bool,
+}
+
+/// Shared handle to the simulation clock. The simulation time
+/// starts at zero when the clock is created.
+#[derive(Clone)]
+pub struct Clock {
+    state: Arc<Mutex<State>>,
+    changed: Arc<Notify>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock {
+            state: Arc::new(Mutex::new(State {
+                origin: Duration::ZERO,
+                real_origin: Instant::now(),
+                rate: 1.,
+                paused: false,
+            })),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Current simulation time.
+    pub fn now(&self) -> Duration {
+        self.state.lock().unwrap().now()
+    }
+
+    pub fn state(&self) -> ClockState {
+        let state = self.state.lock().unwrap();
+        ClockState {
+            time: state.now().as_secs_f64(),
+            rate: state.rate,
+            paused: state.paused,
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        let mut state = self.state.lock().unwrap();
+        state.rebase();
+        f(&mut state);
+        self.changed.notify_waiters();
+    }
+
+    pub fn pause(&self) {
+        self.update(|state| state.paused = true)
+    }
+
+    pub fn resume(&self) {
+        self.update(|state| state.paused = false)
+    }
+
+    /// Set the number of simulation seconds elapsed per real second.
+    /// Fails if the rate is not a positive number.
+    pub fn set_rate(&self, rate: f64) -> Result<(), String> {
+        if !rate.is_finite() || rate <= 0. {
+            return Err(format!("invalid clock rate {}", rate));
+        }
+        self.update(|state| state.rate = rate);
+        Ok(())
+    }
+
+    /// Move the simulation time forward, waking up the tasks
+    /// whose deadline is passed.
+    pub fn advance(&self, duration: Duration) {
+        self.update(|state| state.origin += duration)
+    }
+
+    /// Wait until the simulation time reaches the deadline.
+    pub async fn sleep_until(&self, deadline: Duration) {
+        loop {
+            // Register for changes before reading the state
+            // to not miss any update.
+            let changed = self.changed.notified();
+            let (now, rate, paused) = {
+                let state = self.state.lock().unwrap();
+                (state.now(), state.rate, state.paused)
+            };
+            if now >= deadline {
+                return;
+            }
+            if paused {
+                changed.await
+            } else {
+                tokio::select! {
+                    _ = time::sleep((deadline - now).div_f64(rate)) => (),
+                    _ = changed => (),
+                }
+            }
+        }
+    }
+
+    pub async fn sleep(&self, duration: Duration) {
+        self.sleep_until(self.now() + duration).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pause_and_advance() {
+        let clock = Clock::new();
+        clock.pause();
+        let paused = clock.now();
+        time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(clock.now(), paused);
+
+        let sleeper = clock.clone();
+        let mut task = tokio::spawn(async move { sleeper.sleep(Duration::from_secs(3600)).await });
+        assert!(time::timeout(Duration::from_millis(20), &mut task)
+            .await
+            .is_err());
+        clock.advance(Duration::from_secs(3600));
+        time::timeout(Duration::from_secs(1), task)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(clock.now(), paused + Duration::from_secs(3600));
+    }
+
+    #[tokio::test]
+    async fn rate() {
+        let clock = Clock::new();
+        assert!(clock.set_rate(0.).is_err());
+        assert!(clock.set_rate(f64::INFINITY).is_err());
+        clock.set_rate(1000.).unwrap();
+        let start = Instant::now();
+        clock.sleep(Duration::from_secs(10)).await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(clock.now() >= Duration::from_secs(10));
+    }
+}
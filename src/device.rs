@@ -12,25 +12,41 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::clock::Clock;
+use crate::info::DeviceInfo;
+use crate::measurement::Measurement;
 use crate::position::Position;
+use crate::profile::{DeviceProfile, UciProtocol};
+use crate::snapshot::DeviceSnapshot;
 use crate::uci_packets::*;
 use crate::MacAddress;
 use crate::PicaCommand;
 
 use std::collections::HashMap;
 use std::iter::Extend;
+use std::time::Duration;
 
 use tokio::sync::mpsc;
 
-use num_traits::FromPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use super::session::{Session, MAX_SESSION};
 
 pub const MAX_DEVICE: usize = 4;
-const UCI_VERSION: u16 = 0x1001; // Version 1.1.0
-const MAC_VERSION: u16 = 0x3001; // Version 1.3.0
-const PHY_VERSION: u16 = 0x3001; // Version 1.3.0
-const TEST_VERSION: u16 = 0x1001; // Version 1.1
+
+// [UCI] 2.0 messages not covered by the generated packets.
+pub const CORE_QUERY_UWBS_TIMESTAMP: u8 = 0x08;
+const SESSION_DATA_CREDIT_NTF: u8 = 0x04;
+const SESSION_DATA_TRANSFER_STATUS_NTF: u8 = 0x05;
+const DATA_MESSAGE_SND: u8 = 0x01;
+const DATA_TRANSFER_STATUS_OK: u8 = 0x01;
+const DATA_TRANSFER_STATUS_ERROR_REJECTED: u8 = 0x04;
+const DATA_TRANSFER_STATUS_INVALID_FORMAT: u8 = 0x07;
+// Offsets in the short address two way range data notification,
+// from the start of the payload.
+const RANGE_DATA_MEASUREMENTS_OFFSET: usize = 25;
+const SHORT_ADDRESS_MEASUREMENT_SIZE: usize = 31;
+const MEASUREMENT_RSSI_OFFSET: usize = 19;
 
 // Capabilities are vendor defined, Android parses capabilities
 // according to these definitions:
@@ -67,8 +83,19 @@ pub const DEFAULT_CAPS_INFO: &[(CapTlvType, &[u8])] = &[
     ),
 ];
 
+/// State of a device built from a snapshot. The state is built
+/// before being applied so that invalid snapshots leave the
+/// device unchanged.
+pub struct RestoredDevice {
+    state: DeviceState,
+    config: HashMap<u8, Vec<u8>>,
+    country_code: [u8; 2],
+    sessions: Vec<(Session, SessionState)>,
+}
+
 pub struct Device {
     handle: usize,
+    profile: DeviceProfile,
     pub mac_address: MacAddress,
     pub position: Position,
     /// [UCI] 5. UWBS Device State Machine
@@ -76,6 +103,7 @@ pub struct Device {
     sessions: HashMap<u32, Session>,
     pub tx: mpsc::Sender<UciPacketPacket>,
     pica_tx: mpsc::Sender<PicaCommand>,
+    clock: Clock,
     config: HashMap<u8, Vec<u8>>,
     country_code: [u8; 2],
 
@@ -85,21 +113,22 @@ pub struct Device {
 impl Device {
     pub fn new(
         device_handle: usize,
+        mac_address: MacAddress,
+        profile: DeviceProfile,
         tx: mpsc::Sender<UciPacketPacket>,
         pica_tx: mpsc::Sender<PicaCommand>,
+        clock: Clock,
     ) -> Self {
-        let mac_address = {
-            let handle = device_handle as u16;
-            MacAddress::Short(handle.to_be_bytes())
-        };
         Device {
             handle: device_handle,
+            position: profile.position.unwrap_or_default(),
+            profile,
             mac_address,
-            position: Position::default(),
             state: DeviceState::DeviceStateError, // Will be overwitten
             sessions: Default::default(),
             tx,
             pica_tx,
+            clock,
             config: HashMap::new(),
             country_code: Default::default(),
             n_active_sessions: 0,
@@ -112,13 +141,13 @@ impl Device {
             return;
         }
 
-        // Send status notification
+        // Send status notification, the host may already be disconnected.
         self.state = device_state;
         let tx = self.tx.clone();
         tokio::spawn(async move {
-            tx.send(DeviceStatusNtfBuilder { device_state }.build().into())
-                .await
-                .unwrap()
+            let _ = tx
+                .send(DeviceStatusNtfBuilder { device_state }.build().into())
+                .await;
         });
     }
 
@@ -126,10 +155,177 @@ impl Device {
         self.set_state(DeviceState::DeviceStateReady);
     }
 
+    pub fn handle(&self) -> usize {
+        self.handle
+    }
+
+    pub fn state(&self) -> DeviceState {
+        self.state
+    }
+
+    /// Drop the sessions and the configuration, the sessions report
+    /// their deinitialization. The reset does not move the device.
+    pub fn reset(&mut self) {
+        let position = self.position;
+        *self = Device::new(
+            self.handle,
+            self.mac_address,
+            self.profile.clone(),
+            self.tx.clone(),
+            self.pica_tx.clone(),
+            self.clock.clone(),
+        );
+        self.position = position;
+    }
+
+    /// Move the device to the error state, the active sessions stop
+    /// ranging and return to the idle state.
+    pub fn set_error(&mut self) {
+        for session in self.sessions.values_mut() {
+            session.stop();
+        }
+        self.n_active_sessions = 0;
+        self.set_state(DeviceState::DeviceStateError);
+    }
+
+    /// Stop the ranging of the sessions while the host is disconnected.
+    /// The sessions keep their state and configuration.
+    pub fn suspend(&mut self) {
+        for session in self.sessions.values_mut() {
+            session.suspend();
+        }
+    }
+
+    /// Attach the device to a reconnected host, identified by the handle
+    /// of its connection. The sessions are resumed if `resume_sessions`
+    /// is set, otherwise the device is reset.
+    pub fn resume(
+        &mut self,
+        device_handle: usize,
+        tx: mpsc::Sender<UciPacketPacket>,
+        resume_sessions: bool,
+    ) {
+        self.handle = device_handle;
+        self.tx = tx.clone();
+        if !resume_sessions {
+            self.reset();
+            self.init();
+            return;
+        }
+        let device_state = self.state;
+        tokio::spawn(async move {
+            let _ = tx
+                .send(DeviceStatusNtfBuilder { device_state }.build().into())
+                .await;
+        });
+        for session in self.sessions.values_mut() {
+            session.resume(device_handle, self.tx.clone());
+        }
+    }
+
+    pub fn info(&self, connected: bool) -> DeviceInfo {
+        let mut sessions: Vec<_> = self
+            .sessions
+            .values()
+            .map(|session| session.info())
+            .collect();
+        sessions.sort_by_key(|session| session.id);
+        DeviceInfo {
+            mac_address: self.mac_address,
+            position: self.position,
+            connected,
+            state: self.state.to_u8().unwrap(),
+            config: self.config.clone().into_iter().collect(),
+            country_code: self.country_code,
+            sessions,
+        }
+    }
+
+    pub fn snapshot(&self) -> DeviceSnapshot {
+        let mut sessions: Vec<_> = self
+            .sessions
+            .values()
+            .map(|session| session.snapshot())
+            .collect();
+        sessions.sort_by_key(|session| session.id);
+        DeviceSnapshot {
+            mac_address: self.mac_address,
+            state: self.state.to_u8().unwrap(),
+            config: self.config.clone().into_iter().collect(),
+            country_code: self.country_code,
+            sessions,
+        }
+    }
+
+    pub fn prepare_restore(&self, snapshot: &DeviceSnapshot) -> Result<RestoredDevice, String> {
+        Ok(RestoredDevice {
+            state: DeviceState::from_u8(snapshot.state)
+                .ok_or(format!("invalid device state {}", snapshot.state))?,
+            config: snapshot.config.clone().into_iter().collect(),
+            country_code: snapshot.country_code,
+            sessions: snapshot
+                .sessions
+                .iter()
+                .map(|session| {
+                    Session::from_snapshot(
+                        session,
+                        self.handle,
+                        self.tx.clone(),
+                        self.pica_tx.clone(),
+                        self.clock.clone(),
+                    )
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Replace the configuration and sessions of the device.
+    /// The host is notified of the new device and session states.
+    pub fn restore(&mut self, restored: RestoredDevice) {
+        self.sessions.clear();
+        self.config = restored.config;
+        self.country_code = restored.country_code;
+        self.n_active_sessions = 0;
+        for (mut session, state) in restored.sessions {
+            if state == SessionState::SessionStateActive {
+                self.n_active_sessions += 1;
+            }
+            session.restore_state(state);
+            self.sessions.insert(session.get_id(), session);
+        }
+        self.set_state(restored.state);
+    }
+
+    /// Ranging intervals of the sessions with ranging started.
+    pub fn get_active_ranging_intervals(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.sessions
+            .values()
+            .filter_map(|session| session.get_active_ranging_interval())
+    }
+
+    pub fn get_sessions(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.values()
+    }
+
     pub fn get_session(&self, session_id: u32) -> Option<&Session> {
         self.sessions.get(&session_id)
     }
 
+    pub fn profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
+
+    /// Replace the capabilities and device information. The MAC address
+    /// and position of the device are kept, and the parameters already
+    /// applied to the sessions are not checked again.
+    pub fn set_profile(&mut self, profile: DeviceProfile) {
+        self.profile = DeviceProfile {
+            mac_address: self.profile.mac_address,
+            position: self.profile.position,
+            ..profile
+        };
+    }
+
     pub fn get_session_mut(&mut self, session_id: u32) -> Option<&mut Session> {
         self.sessions.get_mut(&session_id)
     }
@@ -145,8 +341,7 @@ impl Device {
             ResetConfig::UwbsReset => StatusCode::UciStatusOk,
         };
 
-        *self = Device::new(self.handle, self.tx.clone(), self.pica_tx.clone());
-
+        self.reset();
         DeviceResetRspBuilder { status }.build()
     }
 
@@ -156,11 +351,11 @@ impl Device {
         assert_eq!(self.state, DeviceState::DeviceStateReady);
         GetDeviceInfoRspBuilder {
             status: StatusCode::UciStatusOk,
-            uci_version: UCI_VERSION,
-            mac_version: MAC_VERSION,
-            phy_version: PHY_VERSION,
-            uci_test_version: TEST_VERSION,
-            vendor_spec_info: Vec::new(),
+            uci_version: self.profile.uci_version,
+            mac_version: self.profile.mac_version,
+            phy_version: self.profile.phy_version,
+            uci_test_version: self.profile.test_version,
+            vendor_spec_info: self.profile.vendor_spec_info.clone(),
         }
         .build()
     }
@@ -173,11 +368,13 @@ impl Device {
             "Boundary flag is true, implement fragmentation"
         );
 
-        let caps = DEFAULT_CAPS_INFO
+        let caps = self
+            .profile
+            .capabilities
             .iter()
             .map(|(id, value)| CapTlv {
-                t: *id,
-                v: (*value).into(),
+                t: CapTlvType::from_u8(*id).unwrap(),
+                v: value.clone(),
             })
             .collect();
 
@@ -219,7 +416,7 @@ impl Device {
         );
 
         let (status, parameters) = if invalid_config_status.is_empty() {
-            self.config.extend(valid_parameters.into_iter());
+            self.config.extend(valid_parameters);
             (StatusCode::UciStatusOk, Vec::new())
         } else {
             (StatusCode::UciStatusInvalidParam, invalid_config_status)
@@ -280,6 +477,8 @@ impl Device {
 
         let status = if self.sessions.len() >= MAX_SESSION {
             StatusCode::UciStatusMaxSessionsExceeded
+        } else if !self.profile.supports_session_type(session_type) {
+            StatusCode::UciStatusRejected
         } else {
             match self.sessions.insert(
                 session_id,
@@ -289,6 +488,7 @@ impl Device {
                     self.handle,
                     self.tx.clone(),
                     self.pica_tx.clone(),
+                    self.clock.clone(),
                 ),
             ) {
                 Some(_) => StatusCode::UciStatusSessionDuplicate,
@@ -364,7 +564,144 @@ impl Device {
         .build()
     }
 
+    fn command_query_uwbs_timestamp(&self) -> UciResponsePacket {
+        println!("[{}] Query UWBS timestamp", self.handle);
+
+        // The command is unknown in UCI 1.x. The timestamp is the
+        // simulation time in microseconds.
+        let payload = match self.profile.protocol() {
+            UciProtocol::V1 => vec![StatusCode::UciStatusUnknownOid.to_u8().unwrap()],
+            UciProtocol::V2 => {
+                let mut payload = vec![StatusCode::UciStatusOk.to_u8().unwrap()];
+                payload.extend((self.clock.now().as_micros() as u64).to_le_bytes());
+                payload
+            }
+        };
+        UciResponseBuilder {
+            group_id: GroupId::Core,
+            opcode: CORE_QUERY_UWBS_TIMESTAMP,
+            payload: Some(payload.into()),
+        }
+        .build()
+    }
+
+    /// Handle a data packet sent by the host. cf. [UCI] 2.0 6.5
+    /// DATA_MESSAGE_SND is acknowledged with the data credit and data
+    /// transfer status notifications, the application data is not
+    /// delivered to the peers. Data packets are ignored in UCI 1.x.
+    pub fn data(&mut self, packet: &[u8]) -> Vec<UciPacketPacket> {
+        println!("[{}] Data message", self.handle);
+        if self.profile.protocol() != UciProtocol::V2
+            || packet[0] & 0xf != DATA_MESSAGE_SND
+            || packet.len() < 8
+        {
+            println!("  ignored");
+            return Vec::new();
+        }
+
+        // Session handle, destination address, UCI sequence number,
+        // application data size and application data.
+        let session_handle = u32::from_le_bytes(packet[4..8].try_into().unwrap());
+        let (sequence_number, status) = match packet.get(16..20) {
+            Some(header)
+                if packet.len() == 20 + u16::from_le_bytes([header[2], header[3]]) as usize =>
+            {
+                let active = self
+                    .sessions
+                    .get(&session_handle)
+                    .map(|session| session.state())
+                    == Some(SessionState::SessionStateActive);
+                (
+                    [header[0], header[1]],
+                    if active {
+                        DATA_TRANSFER_STATUS_OK
+                    } else {
+                        DATA_TRANSFER_STATUS_ERROR_REJECTED
+                    },
+                )
+            }
+            _ => ([0, 0], DATA_TRANSFER_STATUS_INVALID_FORMAT),
+        };
+        println!("  session_handle=0x{:x}", session_handle);
+        println!("  status={}", status);
+
+        let credit = [&session_handle.to_le_bytes()[..], &[1]].concat();
+        let transfer_status = [
+            &session_handle.to_le_bytes()[..],
+            &sequence_number,
+            &[status, (status == DATA_TRANSFER_STATUS_OK) as u8],
+        ]
+        .concat();
+        [
+            (SESSION_DATA_CREDIT_NTF, credit),
+            (SESSION_DATA_TRANSFER_STATUS_NTF, transfer_status),
+        ]
+        .into_iter()
+        .map(|(opcode, payload)| {
+            UciNotificationBuilder {
+                group_id: GroupId::RangingSessionControl,
+                opcode,
+                payload: Some(payload.into()),
+            }
+            .build()
+            .into()
+        })
+        .collect()
+    }
+
+    /// Build the range data notification of a session, for measurements
+    /// with peers identified by their short address.
+    /// [UCI] 2.0 8.3 reports the received signal strength of each
+    /// measurement in the first reserved octet following the slot index,
+    /// the layout is unchanged otherwise. The session handle of the
+    /// primary session is null, as sub-sessions are not supported.
+    pub fn range_data_notification(
+        &self,
+        session_id: u32,
+        measurements: Vec<(u16, Measurement)>,
+    ) -> UciPacketPacket {
+        let session = self.sessions.get(&session_id).unwrap();
+        // TODO: support extended address
+        let notification: UciPacketPacket = ShortMacTwoWayRangeDataNtfBuilder {
+            sequence_number: session.sequence_number,
+            session_id,
+            rcr_indicator: 0,            //TODO
+            current_ranging_interval: 0, //TODO
+            two_way_ranging_measurements: measurements
+                .iter()
+                .map(|(mac_address, measurement)| {
+                    measurement.to_short_address_measurement(*mac_address)
+                })
+                .collect(),
+        }
+        .build()
+        .into();
+
+        match self.profile.protocol() {
+            UciProtocol::V1 => notification,
+            UciProtocol::V2 => {
+                let opcode = notification.get_opcode();
+                let mut payload = notification.to_bytes()[4..].to_vec();
+                for (index, (_, measurement)) in measurements.iter().enumerate() {
+                    payload[RANGE_DATA_MEASUREMENTS_OFFSET
+                        + index * SHORT_ADDRESS_MEASUREMENT_SIZE
+                        + MEASUREMENT_RSSI_OFFSET] = measurement.uci_rssi();
+                }
+                UciNotificationBuilder {
+                    group_id: GroupId::RangingSessionControl,
+                    opcode,
+                    payload: Some(payload.into()),
+                }
+                .build()
+                .into()
+            }
+        }
+    }
+
     pub fn command(&mut self, cmd: UciCommandPacket) -> UciResponsePacket {
+        if cmd.get_group_id() == GroupId::Core && cmd.get_opcode() == CORE_QUERY_UWBS_TIMESTAMP {
+            return self.command_query_uwbs_timestamp();
+        }
         match cmd.specialize() {
             // Handle commands for this device
             UciCommandChild::CoreCommand(core_command) => match core_command.specialize() {
@@ -380,7 +717,23 @@ impl Device {
                 // Session commands directly handled at Device level
                 match session_command.specialize() {
                     SessionCommandChild::SessionInitCmd(cmd) => {
-                        return self.command_session_init(cmd).into();
+                        let session_id = cmd.get_session_id();
+                        let response = self.command_session_init(cmd);
+                        return match self.profile.protocol() {
+                            UciProtocol::V1 => response.into(),
+                            // [UCI] 2.0 returns the session handle used by the
+                            // following commands, which is the session id.
+                            UciProtocol::V2 => {
+                                let mut payload = vec![response.get_status().to_u8().unwrap()];
+                                payload.extend(session_id.to_le_bytes());
+                                UciResponseBuilder {
+                                    group_id: GroupId::SessionConfig,
+                                    opcode: response.get_opcode(),
+                                    payload: Some(payload.into()),
+                                }
+                                .build()
+                            }
+                        };
                     }
                     SessionCommandChild::SessionDeinitCmd(cmd) => {
                         return self.command_session_deinit(cmd).into();
@@ -402,7 +755,7 @@ impl Device {
                     _ => panic!("Unsupported session command type"),
                 };
 
-                if let Some(session) = self.get_session_mut(session_id) {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
                     // There is a session matching the session_id in the command
                     // Pass the command through
                     match session_command.specialize() {
@@ -410,7 +763,9 @@ impl Device {
                         | SessionCommandChild::SessionGetAppConfigCmd(_)
                         | SessionCommandChild::SessionGetStateCmd(_)
                         | SessionCommandChild::SessionUpdateControllerMulticastListCmd(_) => {
-                            session.session_command(session_command).into()
+                            session
+                                .session_command(session_command, &self.profile)
+                                .into()
                         }
                         _ => panic!("Unsupported session command"),
                     }
@@ -513,3 +868,141 @@ impl Device {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_device(uci_version: u16) -> (Device, mpsc::Receiver<UciPacketPacket>) {
+        let (tx, rx) = mpsc::channel(MAX_SESSION);
+        let (pica_tx, _) = mpsc::channel(1);
+        let profile = DeviceProfile {
+            uci_version,
+            ..Default::default()
+        };
+        let device = Device::new(
+            0,
+            MacAddress::Short([0, 1]),
+            profile,
+            tx,
+            pica_tx,
+            Clock::new(),
+        );
+        (device, rx)
+    }
+
+    fn session_init(device: &mut Device, session_id: u32) -> Vec<u8> {
+        device
+            .command(
+                SessionInitCmdBuilder {
+                    session_id,
+                    session_type: SessionType::FiraRangingSession,
+                }
+                .build()
+                .into(),
+            )
+            .to_bytes()
+            .to_vec()
+    }
+
+    fn data_message(session_handle: u32, sequence_number: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![DATA_MESSAGE_SND, 0];
+        packet.extend((16 + data.len() as u16).to_le_bytes());
+        packet.extend(session_handle.to_le_bytes());
+        packet.extend([0; 8]);
+        packet.extend(sequence_number.to_le_bytes());
+        packet.extend((data.len() as u16).to_le_bytes());
+        packet.extend(data);
+        packet
+    }
+
+    fn transfer_status(notifications: Vec<UciPacketPacket>) -> Vec<u8> {
+        assert_eq!(notifications.len(), 2);
+        notifications[1].clone().to_bytes().to_vec()
+    }
+
+    #[tokio::test]
+    async fn session_init_v2() {
+        let (mut device, _rx) = new_device(0x1001);
+        assert_eq!(
+            session_init(&mut device, 0x1234),
+            vec![0x41, 0x00, 0x00, 0x01, 0x00]
+        );
+
+        let (mut device, _rx) = new_device(0x0002);
+        assert_eq!(
+            session_init(&mut device, 0x1234),
+            vec![0x41, 0x00, 0x00, 0x05, 0x00, 0x34, 0x12, 0x00, 0x00]
+        );
+        // The session handle is returned with the duplicated session status.
+        assert_eq!(
+            session_init(&mut device, 0x1234),
+            vec![0x41, 0x00, 0x00, 0x05, 0x12, 0x34, 0x12, 0x00, 0x00]
+        );
+    }
+
+    #[tokio::test]
+    async fn data() {
+        let (mut device, _rx) = new_device(0x1001);
+        session_init(&mut device, 1);
+        assert!(device.data(&data_message(1, 0x2a, &[1, 2, 3])).is_empty());
+
+        let (mut device, _rx) = new_device(0x0002);
+        session_init(&mut device, 1);
+
+        // The session is not active.
+        let notifications = device.data(&data_message(1, 0x2a, &[1, 2, 3]));
+        assert_eq!(
+            notifications[0].clone().to_bytes().to_vec(),
+            vec![0x62, 0x04, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x01]
+        );
+        assert_eq!(
+            transfer_status(notifications),
+            vec![0x62, 0x05, 0x00, 0x08, 0x01, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x04, 0x00]
+        );
+
+        device
+            .get_session_mut(1)
+            .unwrap()
+            .restore_state(SessionState::SessionStateActive);
+        assert_eq!(
+            transfer_status(device.data(&data_message(1, 0x2a, &[1, 2, 3]))),
+            vec![0x62, 0x05, 0x00, 0x08, 0x01, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x01, 0x01]
+        );
+
+        // The application data size does not match the packet length.
+        let mut packet = data_message(1, 0x2a, &[1, 2, 3]);
+        packet.pop();
+        assert_eq!(
+            transfer_status(device.data(&packet)),
+            vec![0x62, 0x05, 0x00, 0x08, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00]
+        );
+    }
+
+    #[tokio::test]
+    async fn range_data_rssi() {
+        let measurement = Measurement {
+            rssi: -60.,
+            ..Measurement::new(&Position::default(), &Position::default())
+        };
+        // Octet following the slot index of the first measurement.
+        let rssi_offset = 4 + RANGE_DATA_MEASUREMENTS_OFFSET + MEASUREMENT_RSSI_OFFSET;
+        for (uci_version, rssi) in [(0x1001, 0), (0x0002, 120)] {
+            let (mut device, _rx) = new_device(uci_version);
+            session_init(&mut device, 1);
+            let notification = device
+                .range_data_notification(1, vec![(0x0002, measurement)])
+                .to_bytes();
+            assert_eq!(
+                notification.len(),
+                4 + RANGE_DATA_MEASUREMENTS_OFFSET + SHORT_ADDRESS_MEASUREMENT_SIZE
+            );
+            assert_eq!(&notification[..2], &[0x62, 0x00]);
+            assert_eq!(
+                notification[rssi_offset - 19..rssi_offset - 17],
+                [0x02, 0x00]
+            );
+            assert_eq!(notification[rssi_offset], rssi);
+        }
+    }
+}
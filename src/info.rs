@@ -0,0 +1,115 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Description of the UCI devices and sessions, for debugging.
+
+use crate::mac_address::MacAddress;
+use crate::position::Position;
+use crate::session::{DeviceRole, DeviceType};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// App configuration of a session, decoded from the parameters
+/// set by the host. Enumerations are reported with their UCI values.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppConfigInfo {
+    pub device_type: Option<DeviceType>,
+    pub device_role: Option<DeviceRole>,
+    pub mac_address_mode: u8,
+    pub device_mac_address: MacAddress,
+    pub number_of_controlees: usize,
+    pub dst_mac_addresses: Vec<MacAddress>,
+    /// Ranging interval in milliseconds.
+    pub ranging_interval: u64,
+    /// Slot duration in RSTU.
+    pub slot_duration: u16,
+    pub channel_number: u8,
+    pub multi_node_mode: u8,
+    pub tx_adaptive_payload_power: bool,
+    pub sts_config: u8,
+    pub vendor_id: Option<[u8; 2]>,
+    pub static_sts_iv: Option<[u8; 6]>,
+}
+
+/// State of a UCI session. Enumerations are reported
+/// with their UCI values.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: u32,
+    pub session_type: u8,
+    pub state: u8,
+    pub sequence_number: u32,
+    /// Number of range data notifications sent since the
+    /// session was created.
+    pub ranging_count: u32,
+    pub app_config: AppConfigInfo,
+    /// App configuration parameters set by the host,
+    /// indexed by TLV type.
+    pub raw_app_config: BTreeMap<u8, Vec<u8>>,
+}
+
+/// State of a UCI device, with its sessions.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub mac_address: MacAddress,
+    #[serde(flatten)]
+    pub position: Position,
+    /// False for the virtual devices, and the devices waiting
+    /// for their host to reconnect.
+    pub connected: bool,
+    pub state: u8,
+    /// Device configuration parameters, indexed by TLV type.
+    pub config: BTreeMap<u8, Vec<u8>>,
+    pub country_code: [u8; 2],
+    pub sessions: Vec<SessionInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::Session;
+    use crate::snapshot::SessionSnapshot;
+    use crate::{Clock, MacAddress};
+    use std::collections::BTreeMap;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn session_info() {
+        let snapshot = SessionSnapshot {
+            id: 42,
+            session_type: 0,
+            state: 0,
+            sequence_number: 7,
+            app_config: BTreeMap::from([
+                (0x4, vec![5]),
+                (0x5, vec![1]),
+                (0x7, vec![0, 2]),
+                (0x9, vec![100, 0, 0, 0]),
+            ]),
+        };
+        let (tx, _) = mpsc::channel(1);
+        let (pica_tx, _) = mpsc::channel(1);
+        let (session, _) = Session::from_snapshot(&snapshot, 0, tx, pica_tx, Clock::new()).unwrap();
+        let info = session.info();
+        assert_eq!(info.id, 42);
+        assert_eq!(info.sequence_number, 7);
+        assert_eq!(info.ranging_count, 0);
+        assert_eq!(info.app_config.channel_number, 5);
+        assert_eq!(info.app_config.ranging_interval, 100);
+        assert_eq!(
+            info.app_config.dst_mac_addresses,
+            vec![MacAddress::Short([0, 2])]
+        );
+        assert_eq!(info.raw_app_config, snapshot.app_config);
+    }
+}
@@ -15,18 +15,21 @@
 use anyhow::Result;
 use bytes::{Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fmt::Display;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Debug, Display};
 use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
-use tokio::io::AsyncReadExt;
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::sync::{broadcast, mpsc, oneshot};
 
 use num_traits::{FromPrimitive, ToPrimitive};
 
 mod pcapng;
 
+mod clock;
+pub use clock::{Clock, ClockState};
+
 mod position;
 pub use position::Position;
 
@@ -35,27 +38,83 @@ use uci_packets::StatusCode as UciStatusCode;
 use uci_packets::*;
 
 mod device;
-use device::{Device, MAX_DEVICE};
+use device::{Device, CORE_QUERY_UWBS_TIMESTAMP, MAX_DEVICE};
+
+mod profile;
+pub use profile::DeviceProfile;
+
+// Vendor command selecting the persistent identity of a UCI device,
+// the payload is the UTF-8 encoded client identifier.
+const PICA_SET_CLIENT_ID: u8 = 0x00;
 
 mod session;
 use session::MAX_SESSION;
+pub use session::{DeviceRole, DeviceType};
 
 mod mac_address;
-pub use mac_address::MacAddress;
+pub use mac_address::{MacAddress, MacAddressRange};
+
+mod measurement;
+use measurement::Measurement;
+
+mod noise;
+use noise::Noise;
+pub use noise::NoiseModel;
+
+mod obstacle;
+pub use obstacle::Obstacle;
+
+mod zone;
+pub use zone::Zone;
+
+mod link_budget;
+use link_budget::LinkBudget;
+
+mod antenna;
+pub use antenna::AntennaPattern;
+
+mod anchor;
+use anchor::Anchor;
+pub use anchor::{AnchorConfig, StsKey};
+
+mod trajectory;
+pub use trajectory::{Interpolation, Trajectory, Waypoint};
+
+mod scene;
+pub use scene::{Scene, SceneDevice};
+
+mod scenario;
+pub use scenario::{Scenario, ScenarioError};
+
+mod virtual_device;
+pub use virtual_device::{DeviceNotification, RangeMeasurement, VirtualDevice, VirtualSession};
+
+mod info;
+pub use info::{AppConfigInfo, DeviceInfo, SessionInfo};
+
+mod snapshot;
+pub use snapshot::{AntennaSnapshot, DeviceSnapshot, NoiseSnapshot, SessionSnapshot, Snapshot};
+use trajectory::{Motion, MOTION_UPDATE_INTERVAL};
 
 // UCI Generic Specification v1.1.0 § 4.4
 const HEADER_SIZE: usize = 4;
 const MAX_PAYLOAD_SIZE: usize = 255;
 const MAX_PACKET_SIZE: usize = HEADER_SIZE + MAX_PAYLOAD_SIZE;
 
-struct Connection {
-    socket: TcpStream,
+/// Byte stream connecting a UWB host to Pica, e.g. a TCP or
+/// Unix domain socket.
+pub trait UciStream: AsyncRead + AsyncWrite + Debug + Unpin + Send + Sync + 'static {}
+
+impl<S: AsyncRead + AsyncWrite + Debug + Unpin + Send + Sync + 'static> UciStream for S {}
+
+struct Connection<S: UciStream> {
+    socket: S,
     buffer: BytesMut,
     pcapng_file: Option<pcapng::File>,
 }
 
-impl Connection {
-    fn new(socket: TcpStream, pcapng_file: Option<pcapng::File>) -> Self {
+impl<S: UciStream> Connection<S> {
+    fn new(socket: S, pcapng_file: Option<pcapng::File>) -> Self {
         Connection {
             socket,
             buffer: BytesMut::with_capacity(MAX_PACKET_SIZE),
@@ -63,20 +122,31 @@ impl Connection {
         }
     }
 
+    /// Read the next UCI packet. Packets are delimited with the payload
+    /// length of their header, as byte streams can split or merge them.
+    /// The payload length of data packets is encoded on two octets.
     async fn read(&mut self) -> Result<Option<BytesMut>> {
-        let len = self.socket.read_buf(&mut self.buffer).await?;
-        if len == 0 {
-            return Ok(None);
-        }
+        loop {
+            if self.buffer.len() >= HEADER_SIZE {
+                let len = HEADER_SIZE
+                    + match self.buffer[0] >> 5 {
+                        0 => u16::from_le_bytes([self.buffer[2], self.buffer[3]]) as usize,
+                        _ => self.buffer[3] as usize,
+                    };
+                if self.buffer.len() >= len {
+                    let bytes = self.buffer.split_to(len);
+                    if let Some(ref mut pcapng_file) = self.pcapng_file {
+                        pcapng_file.write(&bytes, pcapng::Direction::Tx).await?
+                    }
+                    return Ok(Some(bytes));
+                }
+            }
 
-        if let Some(ref mut pcapng_file) = self.pcapng_file {
-            pcapng_file
-                .write(&self.buffer, pcapng::Direction::Tx)
-                .await?
+            let len = self.socket.read_buf(&mut self.buffer).await?;
+            if len == 0 {
+                return Ok(None);
+            }
         }
-
-        let bytes = self.buffer.split_to(self.buffer.len());
-        Ok(Some(bytes))
     }
 
     async fn write(&mut self, packet: Bytes) -> Result<()> {
@@ -84,7 +154,7 @@ impl Connection {
             pcapng_file.write(&packet, pcapng::Direction::Rx).await?
         }
 
-        let _ = self.socket.try_write(&packet)?;
+        self.socket.write_all(&packet).await?;
         Ok(())
     }
 }
@@ -97,42 +167,174 @@ pub enum PicaCommandError {
     DeviceAlreadyExists(MacAddress),
     #[error("Device not found: {0}")]
     DeviceNotFound(MacAddress),
+    #[error("Device not connected: {0}")]
+    DeviceNotConnected(MacAddress),
+    #[error("Session not found: {0} 0x{1:x}")]
+    SessionNotFound(MacAddress, u32),
+    #[error("Obstacle already exists: {0}")]
+    ObstacleAlreadyExists(String),
+    #[error("Obstacle not found: {0}")]
+    ObstacleNotFound(String),
+    #[error("Zone already exists: {0}")]
+    ZoneAlreadyExists(String),
+    #[error("Zone not found: {0}")]
+    ZoneNotFound(String),
+    #[error("Invalid zone: {0}")]
+    InvalidZone(String),
+    #[error("Invalid trajectory: {0}")]
+    InvalidTrajectory(String),
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("Invalid anchor configuration: {0}")]
+    InvalidAnchorConfig(String),
+    #[error("Invalid virtual device: {0}")]
+    InvalidVirtualDevice(String),
+    #[error("Invalid device profile: {0}")]
+    InvalidDeviceProfile(String),
+    #[error("MAC address reserved for UCI devices: {0}")]
+    ReservedMacAddress(MacAddress),
+    #[error("Invalid clock rate: {0}")]
+    InvalidClockRate(String),
 }
 
 #[derive(Debug)]
 pub enum PicaCommand {
-    // Connect a new device.
-    Connect(TcpStream),
+    // Connect a new device, with an optional persistent identity.
+    Connect(Box<dyn UciStream>, DeviceProfile, Option<String>),
     // Disconnect the selected device.
     Disconnect(usize),
     // Execute ranging command for selected device and session.
     Ranging(usize, u32),
+    // Ranging event of an initiator anchor
+    AnchorRanging(MacAddress),
     // Execute UCI command received for selected device.
     Command(usize, UciCommandPacket),
+    // Handle UCI data packet received for selected device.
+    Data(usize, Bytes),
     // Init Uci Device
     InitUciDevice(MacAddress, Position, oneshot::Sender<PicaCommandStatus>),
+    // Close the connection of a UCI device host
+    DisconnectUciDevice(MacAddress, oneshot::Sender<PicaCommandStatus>),
+    // Reset a UCI device, as if DEVICE_RESET was received
+    ResetUciDevice(MacAddress, oneshot::Sender<PicaCommandStatus>),
+    // Move a UCI device to the error state
+    SetUciDeviceError(MacAddress, oneshot::Sender<PicaCommandStatus>),
     // Set Position
     SetPosition(MacAddress, Position, oneshot::Sender<PicaCommandStatus>),
     // Create Anchor
     CreateAnchor(MacAddress, Position, oneshot::Sender<PicaCommandStatus>),
     // Destroy Anchor
     DestroyAnchor(MacAddress, oneshot::Sender<PicaCommandStatus>),
+    // Create a UCI device driven by Pica
+    CreateVirtualDevice(
+        VirtualDevice,
+        oneshot::Sender<Result<MacAddress, PicaCommandError>>,
+    ),
+    // Destroy a virtual UCI device
+    DestroyVirtualDevice(MacAddress, oneshot::Sender<PicaCommandStatus>),
+    // Configure the sessions accepted by an anchor
+    SetAnchorConfig(MacAddress, AnchorConfig, oneshot::Sender<PicaCommandStatus>),
+    // Replace the capabilities and device information of a UCI device
+    SetDeviceProfile(
+        MacAddress,
+        DeviceProfile,
+        oneshot::Sender<PicaCommandStatus>,
+    ),
     // Get State
     GetState(oneshot::Sender<Vec<(Category, MacAddress, Position)>>),
+    // Set the noise model of a device, or of the link between two devices
+    SetNoiseModel(
+        MacAddress,
+        Option<MacAddress>,
+        NoiseModel,
+        oneshot::Sender<PicaCommandStatus>,
+    ),
+    // Create Obstacle
+    CreateObstacle(String, Obstacle, oneshot::Sender<PicaCommandStatus>),
+    // Destroy Obstacle
+    DestroyObstacle(String, oneshot::Sender<PicaCommandStatus>),
+    // Create Zone
+    CreateZone(String, Zone, oneshot::Sender<PicaCommandStatus>),
+    // Destroy Zone
+    DestroyZone(String, oneshot::Sender<PicaCommandStatus>),
+    // Set the antenna pattern of a device
+    SetAntennaPattern(
+        MacAddress,
+        AntennaPattern,
+        oneshot::Sender<PicaCommandStatus>,
+    ),
+    // Make a device follow a trajectory
+    SetTrajectory(MacAddress, Trajectory, oneshot::Sender<PicaCommandStatus>),
+    // Stop the trajectory of a device
+    ClearTrajectory(MacAddress, oneshot::Sender<PicaCommandStatus>),
+    // Replace the current scene
+    LoadScene(Scene, oneshot::Sender<PicaCommandStatus>),
+    // Save the current scene
+    SaveScene(oneshot::Sender<Scene>),
+    // Capture the full state of the simulator
+    Snapshot(oneshot::Sender<Snapshot>),
+    // Restore a state captured with Snapshot
+    Restore(Snapshot, oneshot::Sender<PicaCommandStatus>),
+    // Pause the simulation clock
+    PauseClock(oneshot::Sender<PicaCommandStatus>),
+    // Resume the simulation clock
+    ResumeClock(oneshot::Sender<PicaCommandStatus>),
+    // Set the number of simulated seconds per real second
+    SetClockRate(f64, oneshot::Sender<PicaCommandStatus>),
+    // Advance the simulation clock by a number of ranging intervals
+    StepClock(u32, oneshot::Sender<PicaCommandStatus>),
+    // Get the state of the simulation clock
+    GetClock(oneshot::Sender<ClockState>),
+    // Describe the UCI devices and their sessions
+    GetUciDevices(oneshot::Sender<Vec<DeviceInfo>>),
+    // Describe a session of a UCI device
+    GetSession(
+        MacAddress,
+        u32,
+        oneshot::Sender<Result<SessionInfo, PicaCommandError>>,
+    ),
 }
 
 impl Display for PicaCommand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let cmd = match self {
-            PicaCommand::Connect(_) => "Connect",
+            PicaCommand::Connect(_, _, _) => "Connect",
             PicaCommand::Disconnect(_) => "Disconnect",
             PicaCommand::Ranging(_, _) => "Ranging",
+            PicaCommand::AnchorRanging(_) => "AnchorRanging",
             PicaCommand::Command(_, _) => "Command",
+            PicaCommand::Data(_, _) => "Data",
             PicaCommand::InitUciDevice(_, _, _) => "InitUciDevice",
+            PicaCommand::DisconnectUciDevice(_, _) => "DisconnectUciDevice",
+            PicaCommand::ResetUciDevice(_, _) => "ResetUciDevice",
+            PicaCommand::SetUciDeviceError(_, _) => "SetUciDeviceError",
             PicaCommand::SetPosition(_, _, _) => "SetPosition",
             PicaCommand::CreateAnchor(_, _, _) => "CreateAnchor",
             PicaCommand::DestroyAnchor(_, _) => "DestroyAnchor",
+            PicaCommand::CreateVirtualDevice(_, _) => "CreateVirtualDevice",
+            PicaCommand::DestroyVirtualDevice(_, _) => "DestroyVirtualDevice",
+            PicaCommand::SetAnchorConfig(_, _, _) => "SetAnchorConfig",
+            PicaCommand::SetDeviceProfile(_, _, _) => "SetDeviceProfile",
             PicaCommand::GetState(_) => "GetState",
+            PicaCommand::SetNoiseModel(_, _, _, _) => "SetNoiseModel",
+            PicaCommand::CreateObstacle(_, _, _) => "CreateObstacle",
+            PicaCommand::DestroyObstacle(_, _) => "DestroyObstacle",
+            PicaCommand::CreateZone(_, _, _) => "CreateZone",
+            PicaCommand::DestroyZone(_, _) => "DestroyZone",
+            PicaCommand::SetAntennaPattern(_, _, _) => "SetAntennaPattern",
+            PicaCommand::SetTrajectory(_, _, _) => "SetTrajectory",
+            PicaCommand::ClearTrajectory(_, _) => "ClearTrajectory",
+            PicaCommand::LoadScene(_, _) => "LoadScene",
+            PicaCommand::SaveScene(_) => "SaveScene",
+            PicaCommand::Snapshot(_) => "Snapshot",
+            PicaCommand::Restore(_, _) => "Restore",
+            PicaCommand::PauseClock(_) => "PauseClock",
+            PicaCommand::ResumeClock(_) => "ResumeClock",
+            PicaCommand::SetClockRate(_, _) => "SetClockRate",
+            PicaCommand::StepClock(_, _) => "StepClock",
+            PicaCommand::GetClock(_) => "GetClock",
+            PicaCommand::GetUciDevices(_) => "GetUciDevices",
+            PicaCommand::GetSession(_, _, _) => "GetSession",
         };
         write!(f, "{}", cmd)
     }
@@ -153,6 +355,19 @@ pub enum PicaEvent {
         category: Category,
         mac_address: MacAddress,
     },
+    // A UCI device host disconnected, the device stays in the scene
+    // until the end of the reconnect grace period
+    DeviceDisconnected {
+        category: Category,
+        mac_address: MacAddress,
+    },
+    // A UCI device host reconnected during the grace period
+    DeviceReconnected {
+        category: Category,
+        mac_address: MacAddress,
+        #[serde(flatten)]
+        position: Position,
+    },
     // A Device position has changed
     DeviceUpdated {
         category: Category,
@@ -168,27 +383,124 @@ pub enum PicaEvent {
         distance: u16,
         azimuth: i16,
         elevation: i8,
+        /// Received signal strength in dBm, on the default channel.
+        rssi: i8,
+    },
+    // A Device entered a zone
+    ZoneEntered {
+        zone: String,
+        category: Category,
+        mac_address: MacAddress,
+        #[serde(flatten)]
+        position: Position,
+    },
+    // A Device exited a zone, or was removed while inside
+    ZoneExited {
+        zone: String,
+        category: Category,
+        mac_address: MacAddress,
+    },
+    // The host of a UCI device received a device status notification
+    DeviceStateChanged {
+        mac_address: MacAddress,
+        state: u8,
+    },
+    // The host of a UCI device received a session status notification
+    SessionStateChanged {
+        mac_address: MacAddress,
+        session_id: u32,
+        state: u8,
+        reason_code: u8,
+    },
+    // The host of a UCI device received a range data notification
+    RangeData {
+        mac_address: MacAddress,
+        session_id: u32,
+        sequence_number: u32,
+        measurements: Vec<RangeMeasurement>,
     },
+    // A virtual device received a notification
+    VirtualDeviceNotification {
+        mac_address: MacAddress,
+        #[serde(flatten)]
+        notification: DeviceNotification,
+    },
+}
+
+impl PicaEvent {
+    /// Name of the event, as reported by the web server.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PicaEvent::DeviceAdded { .. } => "device-added",
+            PicaEvent::DeviceRemoved { .. } => "device-removed",
+            PicaEvent::DeviceDisconnected { .. } => "device-disconnected",
+            PicaEvent::DeviceReconnected { .. } => "device-reconnected",
+            PicaEvent::DeviceUpdated { .. } => "device-updated",
+            PicaEvent::NeighborUpdated { .. } => "neighbor-updated",
+            PicaEvent::ZoneEntered { .. } => "zone-entered",
+            PicaEvent::ZoneExited { .. } => "zone-exited",
+            PicaEvent::DeviceStateChanged { .. } => "device-state-changed",
+            PicaEvent::SessionStateChanged { .. } => "session-state-changed",
+            PicaEvent::RangeData { .. } => "range-data",
+            PicaEvent::VirtualDeviceNotification { .. } => "virtual-device-notification",
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Category {
     Uci,
     Anchor,
 }
 
-#[derive(Debug, Clone, Copy)]
-struct Anchor {
-    mac_address: MacAddress,
-    position: Position,
-}
-
 pub struct Pica {
     devices: HashMap<usize, Device>,
     anchors: HashMap<MacAddress, Anchor>,
+    /// Noise models applied to all measurements involving a device.
+    noise: HashMap<MacAddress, Noise>,
+    /// Noise models applied to measurements between two devices,
+    /// take precedence over the device noise models.
+    link_noise: HashMap<(MacAddress, MacAddress), Noise>,
+    obstacles: HashMap<String, Obstacle>,
+    /// Zones indexed by name, with the devices located inside.
+    zones: BTreeMap<String, (Zone, HashMap<MacAddress, Category>)>,
+    /// Antenna patterns of the devices, devices without pattern
+    /// have an ideal antenna.
+    antennas: HashMap<MacAddress, AntennaPattern>,
+    /// Trajectories followed by the moving devices.
+    motions: HashMap<MacAddress, Motion>,
+    /// Positions and trajectories assigned by the scene
+    /// to UCI devices, applied when the devices connect.
+    registered_devices: HashMap<MacAddress, SceneDevice>,
+    /// Handles of the UCI devices driven by Pica.
+    virtual_devices: HashSet<usize>,
+    /// Range of the MAC addresses assigned to UCI devices. Anchors
+    /// cannot use the addresses of a reserved range.
+    uci_mac_range: Option<MacAddressRange>,
+    /// MAC addresses and positions of the UCI devices with a persistent
+    /// identity, restored when the devices reconnect.
+    identities: HashMap<String, (MacAddress, Position)>,
+    /// Identities of the connected UCI devices.
+    device_identities: HashMap<usize, String>,
+    /// Duration during which the UCI devices with a persistent identity
+    /// stay in the scene after their host disconnected.
+    reconnect_grace_period: Duration,
+    /// Resume the sessions of the devices reconnected during the
+    /// grace period.
+    resume_sessions: bool,
+    /// Deadlines of the grace period of the disconnected UCI devices.
+    disconnected_devices: HashMap<usize, Duration>,
+    /// Signals closing the connections of the UCI devices.
+    connections: HashMap<usize, oneshot::Sender<()>>,
+    clock: Clock,
     counter: usize,
     rx: mpsc::Receiver<PicaCommand>,
     tx: mpsc::Sender<PicaCommand>,
+    /// Notifications written to the hosts of the UCI devices. The channel
+    /// is unbounded so that the connections never wait for Pica, which
+    /// may itself be waiting for the connections to read its packets.
+    notified_rx: mpsc::UnboundedReceiver<(usize, DeviceNotification)>,
+    notified_tx: mpsc::UnboundedSender<(usize, DeviceNotification)>,
     event_tx: broadcast::Sender<PicaEvent>,
     pcapng_dir: Option<PathBuf>,
 }
@@ -196,6 +508,7 @@ pub struct Pica {
 /// Result of UCI packet parsing.
 enum UciParseResult {
     Ok(UciCommandPacket),
+    Data(Bytes),
     Err(Bytes),
     Skip,
 }
@@ -203,6 +516,11 @@ enum UciParseResult {
 /// Parse incoming UCI packets.
 /// Handle parsing errors by crafting a suitable error response packet.
 fn parse_uci_packet(bytes: &[u8]) -> UciParseResult {
+    // Data packets use a different header format, and are
+    // handled by the device.
+    if bytes[0] >> 5 == 0 {
+        return UciParseResult::Data(Bytes::copy_from_slice(bytes));
+    }
     match UciPacketPacket::parse(bytes) {
         // Parsing error. Determine what error response should be
         // returned to the host:
@@ -217,6 +535,23 @@ fn parse_uci_packet(bytes: &[u8]) -> UciParseResult {
             let group_id = bytes[0] & 0xf;
             let opcode_id = bytes[1] & 0x3f;
 
+            // CORE_QUERY_UWBS_TIMESTAMP was introduced in [UCI] 2.0, and
+            // is answered by the device according to its protocol.
+            if let (Some(MessageType::Command), Some(GroupId::Core), CORE_QUERY_UWBS_TIMESTAMP) = (
+                MessageType::from_u8(message_type),
+                GroupId::from_u8(group_id),
+                opcode_id,
+            ) {
+                return UciParseResult::Ok(
+                    UciCommandBuilder {
+                        group_id: GroupId::Core,
+                        opcode: opcode_id,
+                        payload: None,
+                    }
+                    .build(),
+                );
+            }
+
             let status = match (
                 MessageType::from_u8(message_type),
                 GroupId::from_u8(group_id),
@@ -248,15 +583,57 @@ fn parse_uci_packet(bytes: &[u8]) -> UciParseResult {
     }
 }
 
+/// Select the noise model applied to measurements between two devices:
+/// the model of the link if any, otherwise the model of either device.
+fn select_noise<'a>(
+    noise: &'a mut HashMap<MacAddress, Noise>,
+    link_noise: &'a mut HashMap<(MacAddress, MacAddress), Noise>,
+    mac_address: MacAddress,
+    peer_mac_address: MacAddress,
+) -> Option<&'a mut Noise> {
+    let link = [
+        (mac_address, peer_mac_address),
+        (peer_mac_address, mac_address),
+    ]
+    .into_iter()
+    .find(|link| link_noise.contains_key(link));
+    match link {
+        Some(link) => link_noise.get_mut(&link),
+        None => [mac_address, peer_mac_address]
+            .into_iter()
+            .find(|mac_address| noise.contains_key(mac_address))
+            .and_then(|mac_address| noise.get_mut(&mac_address)),
+    }
+}
+
 impl Pica {
     pub fn new(event_tx: broadcast::Sender<PicaEvent>, pcapng_dir: Option<PathBuf>) -> Self {
         let (tx, rx) = mpsc::channel(MAX_SESSION * MAX_DEVICE);
+        let (notified_tx, notified_rx) = mpsc::unbounded_channel();
         Pica {
             devices: HashMap::new(),
             anchors: HashMap::new(),
+            noise: HashMap::new(),
+            link_noise: HashMap::new(),
+            obstacles: HashMap::new(),
+            zones: BTreeMap::new(),
+            antennas: HashMap::new(),
+            motions: HashMap::new(),
+            registered_devices: HashMap::new(),
+            virtual_devices: HashSet::new(),
+            uci_mac_range: None,
+            identities: HashMap::new(),
+            device_identities: HashMap::new(),
+            reconnect_grace_period: Duration::ZERO,
+            resume_sessions: false,
+            disconnected_devices: HashMap::new(),
+            connections: HashMap::new(),
+            clock: Clock::new(),
             counter: 0,
             rx,
             tx,
+            notified_rx,
+            notified_tx,
             event_tx,
             pcapng_dir,
         }
@@ -266,12 +643,58 @@ impl Pica {
         self.tx.clone()
     }
 
-    fn get_device_mut(&mut self, device_handle: usize) -> Option<&mut Device> {
-        self.devices.get_mut(&device_handle)
+    pub fn clock(&self) -> Clock {
+        self.clock.clone()
+    }
+
+    /// Reserve a range of MAC addresses for the UCI devices.
+    pub fn set_uci_mac_range(&mut self, range: MacAddressRange) -> Result<(), String> {
+        range.validate()?;
+        if let Some(mac_address) = self.anchors.keys().find(|mac| range.contains(mac)) {
+            return Err(format!("anchor {} is in the reserved range", mac_address));
+        }
+        self.uci_mac_range = Some(range);
+        Ok(())
+    }
+
+    /// Keep the UCI devices with a persistent identity in the scene
+    /// for the grace period after their host disconnected.
+    pub fn set_reconnect_grace_period(&mut self, grace_period: Duration, resume_sessions: bool) {
+        self.reconnect_grace_period = grace_period;
+        self.resume_sessions = resume_sessions;
+    }
+
+    /// Return an error if the address is reserved for UCI devices.
+    fn check_anchor_mac_address(&self, mac_address: &MacAddress) -> PicaCommandStatus {
+        match &self.uci_mac_range {
+            Some(range) if range.contains(mac_address) => {
+                Err(PicaCommandError::ReservedMacAddress(*mac_address))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Select the first free address of the UCI device range. Addresses
+    /// remembered for disconnected identities are used last.
+    fn free_uci_mac_address(&self) -> Option<MacAddress> {
+        let range = self.uci_mac_range.unwrap_or_default();
+        let remembered: HashSet<MacAddress> = self
+            .identities
+            .values()
+            .map(|(mac_address, _)| *mac_address)
+            .collect();
+        let free = || {
+            range
+                .addresses()
+                .filter(|mac_address| self.get_category(mac_address).is_none())
+        };
+        free()
+            .find(|mac_address| !remembered.contains(mac_address))
+            .or_else(|| free().next())
     }
 
-    fn get_device(&self, device_handle: usize) -> Option<&Device> {
-        self.devices.get(&device_handle)
+    fn get_device_mut(&mut self, device_handle: usize) -> Option<&mut Device> {
+        self.devices.get_mut(&device_handle)
     }
 
     fn get_category(&self, mac_address: &MacAddress) -> Option<Category> {
@@ -294,24 +717,65 @@ impl Pica {
             .find(|d| d.mac_address == mac_address)
     }
 
+    /// Select a UCI device, failing for the devices waiting
+    /// for their host to reconnect.
+    fn get_connected_device_mut(
+        &mut self,
+        mac_address: MacAddress,
+    ) -> Result<&mut Device, PicaCommandError> {
+        let device = self
+            .devices
+            .values_mut()
+            .find(|device| device.mac_address == mac_address)
+            .ok_or(PicaCommandError::DeviceNotFound(mac_address))?;
+        if self.disconnected_devices.contains_key(&device.handle()) {
+            return Err(PicaCommandError::DeviceNotConnected(mac_address));
+        }
+        Ok(device)
+    }
+
     fn send_event(&self, event: PicaEvent) {
         // An error here means that we have
         // no receivers, so ignore it
         let _ = self.event_tx.send(event);
     }
 
-    async fn connect(&mut self, stream: TcpStream) {
-        let (packet_tx, mut packet_rx) = mpsc::channel(MAX_SESSION);
+    /// Create a UCI device sending its packets to the selected channel.
+    fn add_device(
+        &mut self,
+        mac_address: MacAddress,
+        profile: DeviceProfile,
+        packet_tx: mpsc::Sender<UciPacketPacket>,
+        identity: Option<String>,
+    ) -> usize {
         let device_handle = self.counter;
-        let pica_tx = self.tx.clone();
-        let pcapng_dir = self.pcapng_dir.clone();
-
-        println!("[{}] Connecting device", device_handle);
-
         self.counter += 1;
-        let mut device = Device::new(device_handle, packet_tx, self.tx.clone());
+        let mut device = Device::new(
+            device_handle,
+            mac_address,
+            profile,
+            packet_tx,
+            self.tx.clone(),
+            self.clock.clone(),
+        );
         device.init();
 
+        if let Some(registered) = self.registered_devices.get(&device.mac_address) {
+            device.position = registered.position;
+            if let Some(trajectory) = &registered.trajectory {
+                // Trajectories are validated when the scene is loaded.
+                let motion = Motion::new(trajectory.clone(), self.clock.now()).unwrap();
+                self.motions.insert(device.mac_address, motion);
+            }
+        }
+        // The last known position takes precedence over the scene.
+        if let Some(identity) = identity {
+            if let Some((_, position)) = self.identities.get(&identity) {
+                device.position = *position;
+            }
+            self.device_identities.insert(device_handle, identity);
+        }
+
         self.send_event(PicaEvent::DeviceAdded {
             category: Category::Uci,
             mac_address: device.mac_address,
@@ -319,6 +783,99 @@ impl Pica {
         });
 
         self.devices.insert(device_handle, device);
+        device_handle
+    }
+
+    /// Allocate the MAC address of a connected UCI device, and add the
+    /// device to the scene. The connection is dropped when no address
+    /// is available.
+    fn connect_new_device(
+        &mut self,
+        profile: DeviceProfile,
+        packet_tx: mpsc::Sender<UciPacketPacket>,
+        identity: Option<String>,
+    ) -> Option<usize> {
+        if let Some(mac_address) = profile.mac_address {
+            if self.get_category(&mac_address).is_some() {
+                println!(
+                    "[{}] {}",
+                    self.counter,
+                    PicaCommandError::DeviceAlreadyExists(mac_address)
+                );
+                return None;
+            }
+        }
+        // Devices without fixed address get back the address of their
+        // identity when it is free.
+        let mac_address = profile
+            .mac_address
+            .or_else(|| {
+                identity
+                    .as_ref()
+                    .and_then(|identity| self.identities.get(identity))
+                    .map(|(mac_address, _)| *mac_address)
+                    .filter(|mac_address| self.get_category(mac_address).is_none())
+            })
+            .or_else(|| self.free_uci_mac_address());
+        let Some(mac_address) = mac_address else {
+            println!("[{}] No MAC address available", self.counter);
+            return None;
+        };
+        Some(self.add_device(mac_address, profile, packet_tx, identity))
+    }
+
+    /// Attach a device disconnected during the grace period to the
+    /// connection of its reconnected host.
+    fn reconnect(&mut self, device_handle: usize, packet_tx: mpsc::Sender<UciPacketPacket>) {
+        println!("[{}] Reconnecting device", device_handle);
+        self.disconnected_devices.remove(&device_handle);
+        let resume_sessions = self.resume_sessions;
+        let device = self.devices.get_mut(&device_handle).unwrap();
+        device.resume(device_handle, packet_tx, resume_sessions);
+        let (mac_address, position) = (device.mac_address, device.position);
+        self.send_event(PicaEvent::DeviceReconnected {
+            category: Category::Uci,
+            mac_address,
+            position,
+        });
+    }
+
+    async fn connect<S: UciStream>(
+        &mut self,
+        stream: S,
+        profile: DeviceProfile,
+        identity: Option<String>,
+    ) {
+        let (packet_tx, mut packet_rx) = mpsc::channel(MAX_SESSION);
+        let pica_tx = self.tx.clone();
+        let notified_tx = self.notified_tx.clone();
+        let pcapng_dir = self.pcapng_dir.clone();
+        let clock = self.clock.clone();
+
+        println!("[{}] Connecting device", self.counter);
+        // The connection is closed when the profile cannot be applied.
+        if let Err(err) = profile.validate() {
+            println!("[{}] Invalid device profile: {}", self.counter, err);
+            return;
+        }
+        let disconnected_device = identity.as_ref().and_then(|identity| {
+            self.disconnected_devices
+                .keys()
+                .find(|device_handle| self.device_identities.get(device_handle) == Some(identity))
+                .copied()
+        });
+        let device_handle = match disconnected_device {
+            Some(device_handle) => {
+                self.reconnect(device_handle, packet_tx);
+                Some(device_handle)
+            }
+            None => self.connect_new_device(profile, packet_tx, identity),
+        };
+        let Some(device_handle) = device_handle else {
+            return;
+        };
+        let (close_tx, mut close_rx) = oneshot::channel();
+        self.connections.insert(device_handle, close_tx);
 
         // Spawn and detach the connection handling task.
         // The task notifies pica when exiting to let it clean
@@ -327,7 +884,7 @@ impl Pica {
             let pcapng_file: Option<pcapng::File> = if let Some(dir) = pcapng_dir {
                 let full_path = dir.join(format!("device-{}.pcapng", device_handle));
                 println!("Recording pcapng to file {}", full_path.as_path().display());
-                Some(pcapng::File::create(full_path).await.unwrap())
+                Some(pcapng::File::create(full_path, clock).await.unwrap())
             } else {
                 None
             };
@@ -343,6 +900,8 @@ impl Pica {
                                 match parse_uci_packet(&packet) {
                                     UciParseResult::Ok(cmd) =>
                                         pica_tx.send(PicaCommand::Command(device_handle, cmd)).await.unwrap(),
+                                    UciParseResult::Data(data) =>
+                                        pica_tx.send(PicaCommand::Data(device_handle, data)).await.unwrap(),
                                     UciParseResult::Err(response) =>
                                         connection.write(response).await.unwrap(),
                                     UciParseResult::Skip => (),
@@ -350,11 +909,21 @@ impl Pica {
                             Ok(None) | Err(_) => break 'outer
                         },
 
-                    // Send response packets to the connected UWB host.
-                    Some(packet) = packet_rx.recv() =>
+                    // Send response packets to the connected UWB host,
+                    // and report the notifications it received.
+                    Some(packet) = packet_rx.recv() => {
+                        let notification = DeviceNotification::from_packet(&packet);
                         if connection.write(packet.to_bytes()).await.is_err() {
                             break 'outer
                         }
+                        if let Some(notification) = notification {
+                            // Pica is only gone when the simulation stops.
+                            let _ = notified_tx.send((device_handle, notification));
+                        }
+                    },
+
+                    // Close the connection on request.
+                    _ = &mut close_rx => break 'outer
                 }
             }
             pica_tx
@@ -364,9 +933,125 @@ impl Pica {
         });
     }
 
+    fn create_virtual_device(
+        &mut self,
+        virtual_device: VirtualDevice,
+        pica_cmd_rsp_tx: oneshot::Sender<Result<MacAddress, PicaCommandError>>,
+    ) {
+        println!("[{}] Create virtual device", self.counter);
+        println!("  virtual_device={:?}", virtual_device);
+
+        let status = match virtual_device
+            .session
+            .as_ref()
+            .map_or(Ok(()), |session| session.validate())
+        {
+            Err(err) => Err(PicaCommandError::InvalidVirtualDevice(err)),
+            Ok(()) => match self.free_uci_mac_address() {
+                None => Err(PicaCommandError::InvalidVirtualDevice(
+                    "no MAC address available".to_owned(),
+                )),
+                Some(mac_address) => {
+                    let (packet_tx, packet_rx) = mpsc::channel(MAX_SESSION);
+                    let profile = DeviceProfile {
+                        position: Some(virtual_device.position),
+                        ..Default::default()
+                    };
+                    let device_handle = self.add_device(mac_address, profile, packet_tx, None);
+                    self.virtual_devices.insert(device_handle);
+                    tokio::spawn(virtual_device::drive(
+                        device_handle,
+                        mac_address,
+                        virtual_device.session,
+                        self.tx.clone(),
+                        packet_rx,
+                        self.event_tx.clone(),
+                    ));
+                    Ok(mac_address)
+                }
+            },
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send create-virtual-device command response: {:?}",
+                err
+            )
+        });
+    }
+
+    fn destroy_virtual_device(
+        &mut self,
+        mac_address: MacAddress,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Destroy virtual device");
+        println!("  mac_address: {}", mac_address);
+
+        let device_handle = self
+            .virtual_devices
+            .iter()
+            .find(|device_handle| self.devices[*device_handle].mac_address == mac_address)
+            .copied();
+        let status = match device_handle {
+            Some(device_handle) => {
+                self.disconnect(device_handle);
+                Ok(())
+            }
+            None => Err(PicaCommandError::DeviceNotFound(mac_address)),
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send destroy-virtual-device command response: {:?}",
+                err
+            )
+        });
+    }
+
     fn disconnect(&mut self, device_handle: usize) {
         println!("[{}] Disconnecting device", device_handle);
+        self.connections.remove(&device_handle);
+
+        // Devices with a persistent identity wait for their host
+        // to reconnect.
+        if !self.reconnect_grace_period.is_zero()
+            && self.device_identities.contains_key(&device_handle)
+        {
+            if let Some(device) = self.devices.get_mut(&device_handle) {
+                device.suspend();
+                let mac_address = device.mac_address;
+                self.disconnected_devices.insert(
+                    device_handle,
+                    self.clock.now() + self.reconnect_grace_period,
+                );
+                self.send_event(PicaEvent::DeviceDisconnected {
+                    category: Category::Uci,
+                    mac_address,
+                });
+                return;
+            }
+        }
+        self.remove_device(device_handle);
+    }
+
+    /// Remove the UCI devices whose reconnect grace period expired.
+    fn expire_disconnected_devices(&mut self) {
+        let now = self.clock.now();
+        let expired: Vec<usize> = self
+            .disconnected_devices
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(device_handle, _)| *device_handle)
+            .collect();
+        for device_handle in expired {
+            println!("[{}] Reconnect grace period expired", device_handle);
+            self.remove_device(device_handle);
+        }
+    }
 
+    fn remove_device(&mut self, device_handle: usize) {
+        self.disconnected_devices.remove(&device_handle);
         match self
             .devices
             .get(&device_handle)
@@ -377,79 +1062,239 @@ impl Pica {
                     category: Category::Uci,
                     mac_address: device.mac_address,
                 });
+                if let Some(identity) = self.device_identities.remove(&device_handle) {
+                    self.identities
+                        .insert(identity, (device.mac_address, device.position));
+                }
+                self.motions.remove(&device.mac_address);
                 self.devices.remove(&device_handle);
+                self.virtual_devices.remove(&device_handle);
             }
             Err(err) => println!("{}", err),
         }
     }
 
+    /// Received signal strength of the link between two positions, in dBm.
+    fn rssi(&self, position: &Position, peer_position: &Position, link_budget: LinkBudget) -> f32 {
+        let crossed = obstacle::crossed(self.obstacles.values(), position, peer_position);
+        link_budget.rssi(
+            (peer_position.point() - position.point()).length(),
+            obstacle::attenuation(&crossed),
+        )
+    }
+
+    /// Simulate a ranging exchange between two devices.
+    fn measure(
+        &mut self,
+        mac_address: MacAddress,
+        position: &Position,
+        peer_mac_address: MacAddress,
+        peer_position: &Position,
+        link_budget: LinkBudget,
+    ) -> Measurement {
+        let crossed = obstacle::crossed(self.obstacles.values(), position, peer_position);
+        let rssi = link_budget.rssi(
+            (peer_position.point() - position.point()).length(),
+            obstacle::attenuation(&crossed),
+        );
+        if !LinkBudget::in_range(rssi) {
+            return Measurement::failed(UciStatusCode::UciStatusRangingRxTimeout);
+        }
+
+        let mut measurement =
+            obstacle::obstruct(&crossed, Measurement::new(position, peer_position));
+
+        if let Some(noise) = select_noise(
+            &mut self.noise,
+            &mut self.link_noise,
+            mac_address,
+            peer_mac_address,
+        ) {
+            measurement = noise.apply(measurement);
+        }
+
+        if measurement.is_ok() {
+            measurement.rssi = rssi;
+            if let Some(antenna) = self.antennas.get(&mac_address) {
+                measurement.local = antenna.apply(measurement.local);
+            }
+            if let Some(antenna) = self.antennas.get(&peer_mac_address) {
+                measurement.remote = antenna.apply(measurement.remote);
+            }
+        }
+        measurement
+    }
+
     async fn ranging(&mut self, device_handle: usize, session_id: u32) {
         println!("[{}] Ranging event", device_handle);
         println!("  session_id={}", session_id);
 
-        let device = self.get_device(device_handle).unwrap();
-        let session = device.get_session(session_id).unwrap();
+        // The sessions of the devices waiting for their host to
+        // reconnect are suspended.
+        if self.disconnected_devices.contains_key(&device_handle) {
+            return;
+        }
+        // The session may be deinitialized or the device removed
+        // while the ranging event is in flight.
+        let Some(device) = self.devices.get(&device_handle) else {
+            return;
+        };
+        let Some(session) = device.get_session(session_id) else {
+            return;
+        };
+        // Controlee sessions are driven by their controller, and
+        // failed devices do not range.
+        if session.get_device_type() == Some(DeviceType::Controlee)
+            || device.state() == DeviceState::DeviceStateError
+        {
+            return;
+        }
+        let mac_address = device.mac_address;
+        let position = device.position;
+        let link_budget = session.get_link_budget();
+        // Peers are either responder anchors, or controlee sessions of
+        // the other connected devices. The controlee hosts receive the
+        // range data notifications as well.
+        let anchors = session
+            .get_dst_mac_addresses()
+            .iter()
+            .filter_map(|mac_address| self.anchors.get(mac_address))
+            .filter(|anchor| !anchor.config().initiates() && anchor.config().accepts(session))
+            .map(|anchor| (anchor.mac_address, anchor.position, None));
+        let devices = self
+            .devices
+            .iter()
+            .filter(|(peer_device_handle, peer)| {
+                **peer_device_handle != device_handle
+                    && !self.disconnected_devices.contains_key(peer_device_handle)
+                    && peer.state() != DeviceState::DeviceStateError
+                    && session.get_dst_mac_addresses().contains(&peer.mac_address)
+            })
+            .filter_map(|(peer_device_handle, peer)| {
+                peer.get_session(session_id)
+                    .filter(|peer_session| peer_session.is_controlee_of(session, mac_address))
+                    .map(|_| (peer.mac_address, peer.position, Some(*peer_device_handle)))
+            });
+        let peers: Vec<_> = anchors.chain(devices).collect();
 
         let mut measurements = Vec::new();
-        session
-            .get_dst_mac_addresses()
+        for (peer_mac_address, peer_position, peer_device_handle) in peers {
+            let measurement = self.measure(
+                mac_address,
+                &position,
+                peer_mac_address,
+                &peer_position,
+                link_budget,
+            );
+
+            // TODO: support extended address
+            match (peer_device_handle, mac_address) {
+                (Some(peer_device_handle), MacAddress::Short(address)) => {
+                    let peer_measurement = (u16::from_be_bytes(address), measurement.reversed());
+                    self.send_range_data(peer_device_handle, session_id, vec![peer_measurement])
+                        .await
+                }
+                (Some(_), MacAddress::Extend(_)) => {
+                    println!("  skipping extended address controller {}", mac_address)
+                }
+                (None, _) => (),
+            }
+            match peer_mac_address {
+                MacAddress::Short(address) => {
+                    measurements.push((u16::from_be_bytes(address), measurement))
+                }
+                MacAddress::Extend(_) => {
+                    println!("  skipping extended address peer {}", peer_mac_address)
+                }
+            }
+        }
+
+        self.send_range_data(device_handle, session_id, measurements)
+            .await;
+    }
+
+    /// Range with the controlee sessions of the connected devices
+    /// matching the configuration of an initiator anchor. The
+    /// controlee hosts receive the range data notifications.
+    async fn anchor_ranging(&mut self, mac_address: MacAddress) {
+        let anchor = match self.anchors.get(&mac_address) {
+            Some(anchor) => anchor,
+            None => return,
+        };
+        println!("[_] Anchor ranging event");
+        println!("  mac_address: {}", mac_address);
+
+        let position = anchor.position;
+        // The sessions of the devices waiting for their host to
+        // reconnect are suspended, and failed devices do not range.
+        let controlees: Vec<_> = self
+            .devices
             .iter()
-            .for_each(|mac_address| {
-                if let Some(anchor) = self.anchors.get(mac_address) {
-                    let local = device
-                        .position
-                        .compute_range_azimuth_elevation(&anchor.position);
-                    let remote = anchor
-                        .position
-                        .compute_range_azimuth_elevation(&device.position);
-
-                    assert!(local.0 == remote.0);
-
-                    // TODO: support extended address
-                    match mac_address {
-                        MacAddress::Short(address) => {
-                            measurements.push(ShortAddressTwoWayRangingMeasurement {
-                                mac_address: u16::from_be_bytes(*address),
-                                status: UciStatusCode::UciStatusOk,
-                                nlos: 0, // in Line Of Sight
-                                distance: local.0,
-                                aoa_azimuth: local.1 as u16,
-                                aoa_azimuth_fom: 100, // Yup, pretty sure about this
-                                aoa_elevation: local.2 as u16,
-                                aoa_elevation_fom: 100, // Yup, pretty sure about this
-                                aoa_destination_azimuth: remote.1 as u16,
-                                aoa_destination_azimuth_fom: 100,
-                                aoa_destination_elevation: remote.2 as u16,
-                                aoa_destination_elevation_fom: 100,
-                                slot_index: 0,
-                            })
-                        }
-                        MacAddress::Extend(_) => unimplemented!(),
-                    }
+            .filter(|(device_handle, device)| {
+                !self.disconnected_devices.contains_key(device_handle)
+                    && device.state() != DeviceState::DeviceStateError
+            })
+            .flat_map(|(device_handle, device)| {
+                device
+                    .get_sessions()
+                    .filter(|session| anchor.controls(session))
+                    .map(|session| {
+                        (
+                            *device_handle,
+                            session.get_id(),
+                            device.mac_address,
+                            device.position,
+                            session.get_link_budget(),
+                        )
+                    })
+            })
+            .collect();
+
+        for (device_handle, session_id, device_mac_address, device_position, link_budget) in
+            controlees
+        {
+            let measurement = self.measure(
+                device_mac_address,
+                &device_position,
+                mac_address,
+                &position,
+                link_budget,
+            );
+
+            // TODO: support extended address
+            let measurements = match mac_address {
+                MacAddress::Short(address) => {
+                    vec![(u16::from_be_bytes(address), measurement)]
                 }
-            });
+                MacAddress::Extend(_) => {
+                    println!("  skipping extended address anchor {}", mac_address);
+                    return;
+                }
+            };
+            self.send_range_data(device_handle, session_id, measurements)
+                .await;
+        }
+    }
 
+    async fn send_range_data(
+        &mut self,
+        device_handle: usize,
+        session_id: u32,
+        measurements: Vec<(u16, Measurement)>,
+    ) {
+        let device = self.get_device_mut(device_handle).unwrap();
+        let notification = device.range_data_notification(session_id, measurements);
         device
             .tx
-            .send(
-                // TODO: support extended address
-                ShortMacTwoWayRangeDataNtfBuilder {
-                    sequence_number: session.sequence_number,
-                    session_id,
-                    rcr_indicator: 0,            //TODO
-                    current_ranging_interval: 0, //TODO
-                    two_way_ranging_measurements: measurements,
-                }
-                .build()
-                .into(),
-            )
+            .send(notification)
             .await
-            .unwrap();
+            .unwrap_or_else(|err| println!("Failed to send UCI notification: {}", err));
 
         let device = self.get_device_mut(device_handle).unwrap();
         let session = device.get_session_mut(session_id).unwrap();
 
         session.sequence_number += 1;
+        session.ranging_count += 1;
     }
 
     async fn command(&mut self, device_handle: usize, cmd: UciCommandPacket) {
@@ -460,6 +1305,32 @@ impl Pica {
             "Boundary flag is true, implement fragmentation"
         );
 
+        if cmd.get_group_id() == GroupId::VendorReservedA && cmd.get_opcode() == PICA_SET_CLIENT_ID
+        {
+            let payload = match cmd.specialize() {
+                UciCommandChild::UciVendor_A_Command(cmd) => match cmd.specialize() {
+                    UciVendor_A_CommandChild::Payload(payload) => payload,
+                    UciVendor_A_CommandChild::None => Bytes::new(),
+                },
+                _ => Bytes::new(),
+            };
+            let status = self.set_client_id(device_handle, &payload);
+            let response = UciResponseBuilder {
+                group_id: GroupId::VendorReservedA,
+                opcode: PICA_SET_CLIENT_ID,
+                payload: Some(vec![status.to_u8().unwrap()].into()),
+            }
+            .build();
+            if let Some(device) = self.devices.get(&device_handle) {
+                device
+                    .tx
+                    .send(response.into())
+                    .await
+                    .unwrap_or_else(|err| println!("Failed to send UCI command response: {}", err));
+            }
+            return;
+        }
+
         match self
             .get_device_mut(device_handle)
             .ok_or_else(|| PicaCommandError::DeviceNotFound(device_handle.into()))
@@ -476,20 +1347,190 @@ impl Pica {
         }
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        loop {
-            use PicaCommand::*;
-            match self.rx.recv().await {
-                Some(Connect(stream)) => {
-                    self.connect(stream).await;
-                }
-                Some(Disconnect(device_handle)) => self.disconnect(device_handle),
-                Some(Ranging(device_handle, session_id)) => {
-                    self.ranging(device_handle, session_id).await;
-                }
-                Some(Command(device_handle, cmd)) => self.command(device_handle, cmd).await,
-                Some(SetPosition(mac_address, position, pica_cmd_rsp_tx)) => {
-                    self.set_position(mac_address, position, pica_cmd_rsp_tx)
+    fn notified(&mut self, device_handle: usize, notification: DeviceNotification) {
+        // The device may be removed while the notification is in flight.
+        let Some(device) = self.devices.get(&device_handle) else {
+            return;
+        };
+        let mac_address = device.mac_address;
+        self.send_event(match notification {
+            DeviceNotification::DeviceStatus { state } => {
+                PicaEvent::DeviceStateChanged { mac_address, state }
+            }
+            DeviceNotification::SessionStatus {
+                session_id,
+                state,
+                reason_code,
+            } => PicaEvent::SessionStateChanged {
+                mac_address,
+                session_id,
+                state,
+                reason_code,
+            },
+            DeviceNotification::RangeData {
+                session_id,
+                sequence_number,
+                measurements,
+            } => PicaEvent::RangeData {
+                mac_address,
+                session_id,
+                sequence_number,
+                measurements,
+            },
+        });
+    }
+
+    /// Bind a UCI device to the identity of a client. A device waiting for
+    /// the client to reconnect is attached to the connection. Otherwise the
+    /// device takes back the MAC address and position last used by the
+    /// client, unless the address is fixed by the profile or used by
+    /// another device.
+    fn set_client_id(&mut self, device_handle: usize, client_id: &[u8]) -> UciStatusCode {
+        let identity = match std::str::from_utf8(client_id) {
+            Ok(client_id) if !client_id.is_empty() => format!("client:{}", client_id),
+            _ => return UciStatusCode::UciStatusInvalidParam,
+        };
+        println!("[{}] Set client identity", device_handle);
+        println!("  identity={}", identity);
+
+        let Some(device) = self.devices.get(&device_handle) else {
+            return UciStatusCode::UciStatusFailed;
+        };
+        let disconnected_device = self
+            .disconnected_devices
+            .keys()
+            .find(|handle| self.device_identities.get(handle) == Some(&identity))
+            .copied();
+        if device.get_sessions().next().is_some()
+            || self.device_identities.iter().any(|(handle, other)| {
+                *handle != device_handle
+                    && Some(*handle) != disconnected_device
+                    && *other == identity
+            })
+        {
+            return UciStatusCode::UciStatusRejected;
+        }
+
+        // A device waiting for its host to reconnect is attached to the
+        // connection, in place of the device created for it.
+        if let Some(disconnected_device) = disconnected_device {
+            let device = self.devices.remove(&device_handle).unwrap();
+            if let Some(previous_identity) = self.device_identities.remove(&device_handle) {
+                self.identities
+                    .insert(previous_identity, (device.mac_address, device.position));
+            }
+            self.motions.remove(&device.mac_address);
+            self.send_event(PicaEvent::DeviceRemoved {
+                category: Category::Uci,
+                mac_address: device.mac_address,
+            });
+
+            let disconnected = self.devices.remove(&disconnected_device).unwrap();
+            self.devices.insert(device_handle, disconnected);
+            self.disconnected_devices.remove(&disconnected_device);
+            self.device_identities.remove(&disconnected_device);
+            self.device_identities.insert(device_handle, identity);
+            self.reconnect(device_handle, device.tx.clone());
+            return UciStatusCode::UciStatusOk;
+        }
+
+        let previous_mac_address = device.mac_address;
+        let fixed_mac_address = device.profile().mac_address.is_some();
+        let stored = self.identities.get(&identity).copied();
+        let mac_address = stored
+            .map(|(mac_address, _)| mac_address)
+            .filter(|mac_address| {
+                !fixed_mac_address
+                    && (*mac_address == previous_mac_address
+                        || self.get_category(mac_address).is_none())
+            })
+            .unwrap_or(previous_mac_address);
+
+        self.device_identities.insert(device_handle, identity);
+        let device = self.get_device_mut(device_handle).unwrap();
+        device.mac_address = mac_address;
+        if let Some((_, position)) = stored {
+            device.position = position;
+        }
+        let position = device.position;
+
+        if mac_address != previous_mac_address {
+            if let Some(motion) = self.motions.remove(&previous_mac_address) {
+                self.motions.insert(mac_address, motion);
+            }
+            self.send_event(PicaEvent::DeviceRemoved {
+                category: Category::Uci,
+                mac_address: previous_mac_address,
+            });
+            self.send_event(PicaEvent::DeviceAdded {
+                category: Category::Uci,
+                mac_address,
+                position,
+            });
+        } else {
+            self.send_event(PicaEvent::DeviceUpdated {
+                category: Category::Uci,
+                mac_address,
+                position,
+            });
+        }
+        UciStatusCode::UciStatusOk
+    }
+
+    async fn data(&mut self, device_handle: usize, data: Bytes) {
+        match self
+            .get_device_mut(device_handle)
+            .ok_or_else(|| PicaCommandError::DeviceNotFound(device_handle.into()))
+        {
+            Ok(device) => {
+                for notification in device.data(&data) {
+                    device
+                        .tx
+                        .send(notification)
+                        .await
+                        .unwrap_or_else(|err| println!("Failed to send UCI notification: {}", err));
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let mut next_motion_update = Duration::ZERO;
+        loop {
+            use PicaCommand::*;
+            let clock = self.clock.clone();
+            let next_expiry = self.disconnected_devices.values().min().copied();
+            let command = tokio::select! {
+                command = self.rx.recv() => command,
+                Some((device_handle, notification)) = self.notified_rx.recv() => {
+                    self.notified(device_handle, notification);
+                    continue;
+                }
+                _ = clock.sleep_until(next_expiry.unwrap_or_default()), if next_expiry.is_some() => {
+                    self.expire_disconnected_devices();
+                    continue;
+                }
+                _ = clock.sleep_until(next_motion_update), if !self.motions.is_empty() => {
+                    self.update_motions();
+                    self.update_zones();
+                    next_motion_update = self.clock.now() + MOTION_UPDATE_INTERVAL;
+                    continue;
+                }
+            };
+            match command {
+                Some(Connect(stream, profile, identity)) => {
+                    self.connect(stream, profile, identity).await;
+                }
+                Some(Disconnect(device_handle)) => self.disconnect(device_handle),
+                Some(Ranging(device_handle, session_id)) => {
+                    self.ranging(device_handle, session_id).await;
+                }
+                Some(AnchorRanging(mac_address)) => self.anchor_ranging(mac_address).await,
+                Some(Command(device_handle, cmd)) => self.command(device_handle, cmd).await,
+                Some(Data(device_handle, data)) => self.data(device_handle, data).await,
+                Some(SetPosition(mac_address, position, pica_cmd_rsp_tx)) => {
+                    self.set_position(mac_address, position, pica_cmd_rsp_tx)
                 }
                 Some(CreateAnchor(mac_address, position, pica_cmd_rsp_tx)) => {
                     self.create_anchor(mac_address, position, pica_cmd_rsp_tx)
@@ -497,18 +1538,112 @@ impl Pica {
                 Some(DestroyAnchor(mac_address, pica_cmd_rsp_tx)) => {
                     self.destroy_anchor(mac_address, pica_cmd_rsp_tx)
                 }
+                Some(CreateVirtualDevice(virtual_device, pica_cmd_rsp_tx)) => {
+                    self.create_virtual_device(virtual_device, pica_cmd_rsp_tx)
+                }
+                Some(DestroyVirtualDevice(mac_address, pica_cmd_rsp_tx)) => {
+                    self.destroy_virtual_device(mac_address, pica_cmd_rsp_tx)
+                }
+                Some(SetAnchorConfig(mac_address, config, pica_cmd_rsp_tx)) => {
+                    self.set_anchor_config(mac_address, config, pica_cmd_rsp_tx)
+                }
+                Some(SetDeviceProfile(mac_address, profile, pica_cmd_rsp_tx)) => {
+                    self.set_device_profile(mac_address, profile, pica_cmd_rsp_tx)
+                }
                 Some(GetState(state_tx)) => self.get_state(state_tx),
+                Some(SetNoiseModel(mac_address, peer_mac_address, model, pica_cmd_rsp_tx)) => {
+                    self.set_noise_model(mac_address, peer_mac_address, model, pica_cmd_rsp_tx)
+                }
+                Some(CreateObstacle(name, obstacle, pica_cmd_rsp_tx)) => {
+                    self.create_obstacle(name, obstacle, pica_cmd_rsp_tx)
+                }
+                Some(DestroyObstacle(name, pica_cmd_rsp_tx)) => {
+                    self.destroy_obstacle(name, pica_cmd_rsp_tx)
+                }
+                Some(CreateZone(name, zone, pica_cmd_rsp_tx)) => {
+                    self.create_zone(name, zone, pica_cmd_rsp_tx)
+                }
+                Some(DestroyZone(name, pica_cmd_rsp_tx)) => {
+                    self.destroy_zone(name, pica_cmd_rsp_tx)
+                }
+                Some(SetAntennaPattern(mac_address, pattern, pica_cmd_rsp_tx)) => {
+                    self.set_antenna_pattern(mac_address, pattern, pica_cmd_rsp_tx)
+                }
+                Some(SetTrajectory(mac_address, trajectory, pica_cmd_rsp_tx)) => {
+                    self.set_trajectory(mac_address, trajectory, pica_cmd_rsp_tx)
+                }
+                Some(ClearTrajectory(mac_address, pica_cmd_rsp_tx)) => {
+                    self.clear_trajectory(mac_address, pica_cmd_rsp_tx)
+                }
+                Some(LoadScene(scene, pica_cmd_rsp_tx)) => {
+                    let status = self.load_scene(scene);
+                    pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+                        println!("Failed to send load-scene command response: {:?}", err)
+                    });
+                }
+                Some(SaveScene(scene_tx)) => scene_tx
+                    .send(self.save_scene())
+                    .unwrap_or_else(|_| println!("Failed to send save-scene command response")),
+                Some(Snapshot(snapshot_tx)) => snapshot_tx
+                    .send(self.snapshot())
+                    .unwrap_or_else(|_| println!("Failed to send snapshot command response")),
+                Some(Restore(snapshot, pica_cmd_rsp_tx)) => {
+                    let status = self.restore(snapshot);
+                    pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+                        println!("Failed to send restore command response: {:?}", err)
+                    });
+                }
+                Some(PauseClock(pica_cmd_rsp_tx)) => {
+                    println!("[_] Pause clock");
+                    self.clock.pause();
+                    pica_cmd_rsp_tx.send(Ok(())).unwrap_or_else(|err| {
+                        println!("Failed to send pause-clock command response: {:?}", err)
+                    });
+                }
+                Some(ResumeClock(pica_cmd_rsp_tx)) => {
+                    println!("[_] Resume clock");
+                    self.clock.resume();
+                    pica_cmd_rsp_tx.send(Ok(())).unwrap_or_else(|err| {
+                        println!("Failed to send resume-clock command response: {:?}", err)
+                    });
+                }
+                Some(SetClockRate(rate, pica_cmd_rsp_tx)) => {
+                    println!("[_] Set clock rate: {}", rate);
+                    let status = self
+                        .clock
+                        .set_rate(rate)
+                        .map_err(PicaCommandError::InvalidClockRate);
+                    pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+                        println!("Failed to send set-clock-rate command response: {:?}", err)
+                    });
+                }
+                Some(StepClock(count, pica_cmd_rsp_tx)) => self.step_clock(count, pica_cmd_rsp_tx),
+                Some(GetUciDevices(devices_tx)) => self.get_uci_devices(devices_tx),
+                Some(GetSession(mac_address, session_id, session_tx)) => {
+                    self.get_session(mac_address, session_id, session_tx)
+                }
+                Some(GetClock(clock_tx)) => clock_tx
+                    .send(self.clock.state())
+                    .unwrap_or_else(|_| println!("Failed to send get-clock command response")),
                 Some(InitUciDevice(mac_address, position, pica_cmd_rsp_tx)) => {
                     self.init_uci_device(mac_address, position, pica_cmd_rsp_tx);
                 }
+                Some(DisconnectUciDevice(mac_address, pica_cmd_rsp_tx)) => {
+                    self.disconnect_uci_device(mac_address, pica_cmd_rsp_tx)
+                }
+                Some(ResetUciDevice(mac_address, pica_cmd_rsp_tx)) => {
+                    self.reset_uci_device(mac_address, pica_cmd_rsp_tx)
+                }
+                Some(SetUciDeviceError(mac_address, pica_cmd_rsp_tx)) => {
+                    self.set_uci_device_error(mac_address, pica_cmd_rsp_tx)
+                }
                 None => (),
             };
+            // Devices can be moved, added or removed by most commands.
+            self.update_zones();
         }
     }
 
-    // TODO: Assign a reserved range of mac addresses for UCI devices
-    // to protect against conflicts  with user defined Anchor addresses
-    // b/246000641
     fn init_uci_device(
         &mut self,
         mac_address: MacAddress,
@@ -532,6 +1667,78 @@ impl Pica {
         });
     }
 
+    fn disconnect_uci_device(
+        &mut self,
+        mac_address: MacAddress,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Disconnect device");
+        println!("  mac_address: {}", mac_address);
+
+        let status = match self
+            .devices
+            .values()
+            .find(|device| device.mac_address == mac_address)
+        {
+            None => Err(PicaCommandError::DeviceNotFound(mac_address)),
+            Some(device) => match self.connections.remove(&device.handle()) {
+                // The connection task reports the disconnection.
+                Some(close_tx) => close_tx
+                    .send(())
+                    .map_err(|_| PicaCommandError::DeviceNotConnected(mac_address)),
+                None => Err(PicaCommandError::DeviceNotConnected(mac_address)),
+            },
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send disconnect-uci-device command response: {:?}",
+                err
+            )
+        });
+    }
+
+    fn reset_uci_device(
+        &mut self,
+        mac_address: MacAddress,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Reset device");
+        println!("  mac_address: {}", mac_address);
+
+        let status = self.get_connected_device_mut(mac_address).map(|device| {
+            device.reset();
+            device.init();
+        });
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send reset-uci-device command response: {:?}",
+                err
+            )
+        });
+    }
+
+    fn set_uci_device_error(
+        &mut self,
+        mac_address: MacAddress,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Set device error");
+        println!("  mac_address: {}", mac_address);
+
+        let status = self
+            .get_connected_device_mut(mac_address)
+            .map(|device| device.set_error());
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send set-uci-device-error command response: {:?}",
+                err
+            )
+        });
+    }
+
     fn set_position(
         &mut self,
         mac_address: MacAddress,
@@ -549,6 +1756,8 @@ impl Pica {
         };
 
         if status.is_ok() {
+            // An explicit position interrupts the trajectory of the device.
+            self.motions.remove(&mac_address);
             status = self.update_position(mac_address, position)
         }
 
@@ -557,6 +1766,27 @@ impl Pica {
         });
     }
 
+    /// Move the devices following a trajectory to their current position.
+    fn update_motions(&mut self) {
+        let now = self.clock.now();
+        let positions: Vec<(MacAddress, Position)> = self
+            .motions
+            .iter()
+            .map(|(mac_address, motion)| (*mac_address, motion.position(now)))
+            .collect();
+        self.motions.retain(|_, motion| !motion.is_finished(now));
+
+        for (mac_address, position) in positions {
+            if let Some(uci_device) = self.get_device_mut_by_mac(mac_address) {
+                uci_device.position = position;
+            } else if let Some(anchor) = self.anchors.get_mut(&mac_address) {
+                anchor.position = position;
+            }
+            self.update_position(mac_address, position)
+                .unwrap_or_else(|err| println!("{}", err));
+        }
+    }
+
     fn update_position(
         &self,
         mac_address: MacAddress,
@@ -581,6 +1811,10 @@ impl Pica {
             if mac_address != device_mac_address {
                 let local = position.compute_range_azimuth_elevation(&device_position);
                 let remote = device_position.compute_range_azimuth_elevation(&position);
+                let rssi = self
+                    .rssi(&position, &device_position, LinkBudget::default())
+                    .round()
+                    .clamp(i8::MIN as f32, i8::MAX as f32) as i8;
 
                 assert!(local.0 == remote.0);
 
@@ -592,6 +1826,7 @@ impl Pica {
                     distance: local.0,
                     azimuth: local.1,
                     elevation: local.2,
+                    rssi,
                 });
 
                 self.send_event(PicaEvent::NeighborUpdated {
@@ -602,6 +1837,7 @@ impl Pica {
                     distance: remote.0,
                     azimuth: remote.1,
                     elevation: remote.2,
+                    rssi,
                 });
             }
         };
@@ -621,6 +1857,8 @@ impl Pica {
         println!("Create anchor: {} {}", mac_address, position);
         let status = if self.get_category(&mac_address).is_some() {
             Err(PicaCommandError::DeviceAlreadyExists(mac_address))
+        } else if let Err(err) = self.check_anchor_mac_address(&mac_address) {
+            Err(err)
         } else {
             self.send_event(PicaEvent::DeviceAdded {
                 category: Category::Anchor,
@@ -631,10 +1869,13 @@ impl Pica {
                 .anchors
                 .insert(
                     mac_address,
-                    Anchor {
+                    Anchor::new(
                         mac_address,
                         position,
-                    },
+                        AnchorConfig::default(),
+                        self.tx.clone(),
+                        self.clock.clone(),
+                    ),
                 )
                 .is_none());
             Ok(())
@@ -656,6 +1897,7 @@ impl Pica {
         let status = if self.anchors.remove(&mac_address).is_none() {
             Err(PicaCommandError::DeviceNotFound(mac_address))
         } else {
+            self.motions.remove(&mac_address);
             self.send_event(PicaEvent::DeviceRemoved {
                 category: Category::Anchor,
                 mac_address,
@@ -667,6 +1909,56 @@ impl Pica {
         })
     }
 
+    fn set_anchor_config(
+        &mut self,
+        mac_address: MacAddress,
+        config: AnchorConfig,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Set anchor config");
+        println!("  mac_address: {}", mac_address);
+        println!("  config={:?}", config);
+
+        let status = match self.anchors.get_mut(&mac_address) {
+            None => Err(PicaCommandError::DeviceNotFound(mac_address)),
+            Some(anchor) => config
+                .validate()
+                .map(|_| anchor.set_config(config))
+                .map_err(PicaCommandError::InvalidAnchorConfig),
+        };
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send set-anchor-config command response: {:?}",
+                err
+            )
+        })
+    }
+
+    fn set_device_profile(
+        &mut self,
+        mac_address: MacAddress,
+        profile: DeviceProfile,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Set device profile");
+        println!("  mac_address: {}", mac_address);
+        println!("  profile={:?}", profile);
+
+        let status = match self.get_device_mut_by_mac(mac_address) {
+            None => Err(PicaCommandError::DeviceNotFound(mac_address)),
+            Some(device) => profile
+                .validate()
+                .map(|_| device.set_profile(profile))
+                .map_err(PicaCommandError::InvalidDeviceProfile),
+        };
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send set-device-profile command response: {:?}",
+                err
+            )
+        })
+    }
+
     fn get_state(&self, state_tx: oneshot::Sender<Vec<(Category, MacAddress, Position)>>) {
         println!("[_] Get State");
 
@@ -684,4 +1976,1070 @@ impl Pica {
             )
             .unwrap();
     }
+
+    fn get_uci_devices(&self, devices_tx: oneshot::Sender<Vec<DeviceInfo>>) {
+        println!("[_] Get UCI devices");
+
+        // Devices are listed in connection order.
+        let mut device_handles: Vec<_> = self.devices.keys().copied().collect();
+        device_handles.sort();
+        let devices = device_handles
+            .iter()
+            .map(|device_handle| {
+                self.devices[device_handle].info(
+                    !self.virtual_devices.contains(device_handle)
+                        && !self.disconnected_devices.contains_key(device_handle),
+                )
+            })
+            .collect();
+        devices_tx.send(devices).unwrap_or_else(|err| {
+            println!("Failed to send get-uci-devices command response: {:?}", err)
+        });
+    }
+
+    fn get_session(
+        &self,
+        mac_address: MacAddress,
+        session_id: u32,
+        session_tx: oneshot::Sender<Result<SessionInfo, PicaCommandError>>,
+    ) {
+        println!("[_] Get session");
+        println!("  mac_address: {}", mac_address);
+        println!("  session_id=0x{:x}", session_id);
+
+        let session = self
+            .devices
+            .values()
+            .find(|device| device.mac_address == mac_address)
+            .ok_or(PicaCommandError::DeviceNotFound(mac_address))
+            .and_then(|device| {
+                device
+                    .get_session(session_id)
+                    .map(|session| session.info())
+                    .ok_or(PicaCommandError::SessionNotFound(mac_address, session_id))
+            });
+        session_tx.send(session).unwrap_or_else(|err| {
+            println!("Failed to send get-session command response: {:?}", err)
+        });
+    }
+
+    fn set_noise_model(
+        &mut self,
+        mac_address: MacAddress,
+        peer_mac_address: Option<MacAddress>,
+        model: NoiseModel,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Set noise model");
+        println!("  mac_address: {}", mac_address);
+        if let Some(peer_mac_address) = peer_mac_address {
+            println!("  peer_mac_address: {}", peer_mac_address);
+        }
+        println!("  model={:?}", model);
+
+        let status = match peer_mac_address {
+            _ if self.get_category(&mac_address).is_none() => {
+                Err(PicaCommandError::DeviceNotFound(mac_address))
+            }
+            Some(peer_mac_address) if self.get_category(&peer_mac_address).is_none() => {
+                Err(PicaCommandError::DeviceNotFound(peer_mac_address))
+            }
+            Some(peer_mac_address) => {
+                self.link_noise.remove(&(peer_mac_address, mac_address));
+                self.link_noise
+                    .insert((mac_address, peer_mac_address), Noise::new(model));
+                Ok(())
+            }
+            None => {
+                self.noise.insert(mac_address, Noise::new(model));
+                Ok(())
+            }
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!("Failed to send set-noise-model command response: {:?}", err)
+        });
+    }
+
+    #[allow(clippy::map_entry)]
+    fn create_obstacle(
+        &mut self,
+        name: String,
+        obstacle: Obstacle,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Create obstacle");
+        println!("  name: {}", name);
+        println!("  obstacle={:?}", obstacle);
+
+        let status = if self.obstacles.contains_key(&name) {
+            Err(PicaCommandError::ObstacleAlreadyExists(name))
+        } else {
+            self.obstacles.insert(name, obstacle);
+            Ok(())
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!("Failed to send create-obstacle command response: {:?}", err)
+        });
+    }
+
+    fn destroy_obstacle(
+        &mut self,
+        name: String,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Destroy obstacle");
+        println!("  name: {}", name);
+
+        let status = match self.obstacles.remove(&name) {
+            Some(_) => Ok(()),
+            None => Err(PicaCommandError::ObstacleNotFound(name)),
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send destroy-obstacle command response: {:?}",
+                err
+            )
+        });
+    }
+
+    #[allow(clippy::map_entry)]
+    fn create_zone(
+        &mut self,
+        name: String,
+        zone: Zone,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Create zone");
+        println!("  name: {}", name);
+        println!("  zone={:?}", zone);
+
+        let status = if self.zones.contains_key(&name) {
+            Err(PicaCommandError::ZoneAlreadyExists(name))
+        } else {
+            zone.validate()
+                .map(|_| {
+                    self.zones.insert(name, (zone, HashMap::new()));
+                })
+                .map_err(PicaCommandError::InvalidZone)
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!("Failed to send create-zone command response: {:?}", err)
+        });
+    }
+
+    fn destroy_zone(&mut self, name: String, pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>) {
+        println!("[_] Destroy zone");
+        println!("  name: {}", name);
+
+        let status = match self.zones.remove(&name) {
+            Some(_) => Ok(()),
+            None => Err(PicaCommandError::ZoneNotFound(name)),
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!("Failed to send destroy-zone command response: {:?}", err)
+        });
+    }
+
+    /// Compare the devices located inside each zone with the previous
+    /// state, and report the zone transitions.
+    fn update_zones(&mut self) {
+        if self.zones.is_empty() {
+            return;
+        }
+        let devices: Vec<(Category, MacAddress, Position)> = self
+            .anchors
+            .values()
+            .map(|anchor| (Category::Anchor, anchor.mac_address, anchor.position))
+            .chain(
+                self.devices
+                    .values()
+                    .map(|device| (Category::Uci, device.mac_address, device.position)),
+            )
+            .collect();
+
+        let mut events = Vec::new();
+        for (name, (zone, inside)) in self.zones.iter_mut() {
+            let mut previous = std::mem::take(inside);
+            for (category, mac_address, position) in &devices {
+                if !zone.contains(position) {
+                    continue;
+                }
+                inside.insert(*mac_address, *category);
+                if previous.remove(mac_address) != Some(*category) {
+                    events.push(PicaEvent::ZoneEntered {
+                        zone: name.clone(),
+                        category: *category,
+                        mac_address: *mac_address,
+                        position: *position,
+                    });
+                }
+            }
+            for (mac_address, category) in previous {
+                events.push(PicaEvent::ZoneExited {
+                    zone: name.clone(),
+                    category,
+                    mac_address,
+                });
+            }
+        }
+        for event in events {
+            self.send_event(event);
+        }
+    }
+
+    fn set_antenna_pattern(
+        &mut self,
+        mac_address: MacAddress,
+        pattern: AntennaPattern,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Set antenna pattern");
+        println!("  mac_address: {}", mac_address);
+        println!("  pattern={:?}", pattern);
+
+        let status = if self.get_category(&mac_address).is_none() {
+            Err(PicaCommandError::DeviceNotFound(mac_address))
+        } else {
+            self.antennas.insert(mac_address, pattern);
+            Ok(())
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send set-antenna-pattern command response: {:?}",
+                err
+            )
+        });
+    }
+
+    fn set_trajectory(
+        &mut self,
+        mac_address: MacAddress,
+        trajectory: Trajectory,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Set trajectory");
+        println!("  mac_address: {}", mac_address);
+        println!("  trajectory={:?}", trajectory);
+
+        let status = if self.get_category(&mac_address).is_none() {
+            Err(PicaCommandError::DeviceNotFound(mac_address))
+        } else {
+            Motion::new(trajectory, self.clock.now())
+                .map(|motion| {
+                    self.motions.insert(mac_address, motion);
+                })
+                .map_err(PicaCommandError::InvalidTrajectory)
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!("Failed to send set-trajectory command response: {:?}", err)
+        });
+    }
+
+    fn clear_trajectory(
+        &mut self,
+        mac_address: MacAddress,
+        pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>,
+    ) {
+        println!("[_] Clear trajectory");
+        println!("  mac_address: {}", mac_address);
+
+        let status = if self.get_category(&mac_address).is_none() {
+            Err(PicaCommandError::DeviceNotFound(mac_address))
+        } else {
+            self.motions.remove(&mac_address);
+            Ok(())
+        };
+
+        pica_cmd_rsp_tx.send(status).unwrap_or_else(|err| {
+            println!(
+                "Failed to send clear-trajectory command response: {:?}",
+                err
+            )
+        });
+    }
+
+    /// Replace the anchors, obstacles, zones and trajectories with the content
+    /// of the scene. The state is left unchanged if the scene is invalid.
+    pub fn load_scene(&mut self, scene: Scene) -> PicaCommandStatus {
+        println!("[_] Load scene");
+
+        let mut mac_addresses = Vec::new();
+        for device in scene.anchors.iter().chain(scene.devices.iter()) {
+            if mac_addresses.contains(&device.mac_address) {
+                return Err(PicaCommandError::DeviceAlreadyExists(device.mac_address));
+            }
+            mac_addresses.push(device.mac_address);
+            if let Some(trajectory) = &device.trajectory {
+                Motion::new(trajectory.clone(), self.clock.now())
+                    .map_err(PicaCommandError::InvalidTrajectory)?;
+            }
+            if let Some(config) = &device.config {
+                config
+                    .validate()
+                    .map_err(PicaCommandError::InvalidAnchorConfig)?;
+            }
+        }
+        for zone in scene.zones.values() {
+            zone.validate().map_err(PicaCommandError::InvalidZone)?;
+        }
+        for anchor in scene.anchors.iter() {
+            self.check_anchor_mac_address(&anchor.mac_address)?;
+        }
+        if let Some(anchor) = scene.anchors.iter().find(|anchor| {
+            self.devices
+                .values()
+                .any(|device| device.mac_address == anchor.mac_address)
+        }) {
+            return Err(PicaCommandError::DeviceAlreadyExists(anchor.mac_address));
+        }
+
+        for mac_address in self.anchors.keys() {
+            self.send_event(PicaEvent::DeviceRemoved {
+                category: Category::Anchor,
+                mac_address: *mac_address,
+            });
+        }
+        self.anchors.clear();
+        self.motions.clear();
+        self.obstacles = scene.obstacles.into_iter().collect();
+        // Devices already inside a zone of the same name
+        // are not reported again.
+        let mut zones = std::mem::take(&mut self.zones);
+        self.zones = scene
+            .zones
+            .into_iter()
+            .map(|(name, zone)| {
+                let inside = zones
+                    .remove(&name)
+                    .map(|(_, inside)| inside)
+                    .unwrap_or_default();
+                (name, (zone, inside))
+            })
+            .collect();
+        self.registered_devices = scene
+            .devices
+            .into_iter()
+            .map(|device| (device.mac_address, device))
+            .collect();
+
+        let now = self.clock.now();
+        for anchor in scene.anchors {
+            let mac_address = anchor.mac_address;
+            self.send_event(PicaEvent::DeviceAdded {
+                category: Category::Anchor,
+                mac_address,
+                position: anchor.position,
+            });
+            self.anchors.insert(
+                mac_address,
+                Anchor::new(
+                    mac_address,
+                    anchor.position,
+                    anchor.config.unwrap_or_default(),
+                    self.tx.clone(),
+                    self.clock.clone(),
+                ),
+            );
+            if let Some(trajectory) = anchor.trajectory {
+                self.motions
+                    .insert(mac_address, Motion::new(trajectory, now).unwrap());
+            }
+        }
+
+        let mut updated = Vec::new();
+        for device in self.devices.values_mut() {
+            if let Some(registered) = self.registered_devices.get(&device.mac_address) {
+                device.position = registered.position;
+                if let Some(trajectory) = &registered.trajectory {
+                    self.motions.insert(
+                        device.mac_address,
+                        Motion::new(trajectory.clone(), now).unwrap(),
+                    );
+                }
+                updated.push((device.mac_address, device.position));
+            }
+        }
+        for (mac_address, position) in updated {
+            self.update_position(mac_address, position)?;
+        }
+
+        Ok(())
+    }
+
+    /// Describe the current anchors, UCI devices, obstacles and zones as a scene.
+    /// UCI devices registered by the scene but not connected are kept.
+    pub fn save_scene(&self) -> Scene {
+        println!("[_] Save scene");
+
+        let scene_device = |mac_address: MacAddress, position: Position| SceneDevice {
+            mac_address,
+            position,
+            trajectory: self
+                .motions
+                .get(&mac_address)
+                .map(|motion| motion.trajectory().clone()),
+            config: None,
+        };
+
+        let mut anchors: Vec<SceneDevice> = self
+            .anchors
+            .values()
+            .map(|anchor| SceneDevice {
+                config: Some(anchor.config().clone())
+                    .filter(|config| *config != AnchorConfig::default()),
+                ..scene_device(anchor.mac_address, anchor.position)
+            })
+            .collect();
+        let mut devices: Vec<SceneDevice> = self
+            .devices
+            .values()
+            .map(|device| scene_device(device.mac_address, device.position))
+            .collect();
+        devices.extend(
+            self.registered_devices
+                .values()
+                .filter(|registered| {
+                    !devices
+                        .iter()
+                        .any(|device| device.mac_address == registered.mac_address)
+                })
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        anchors.sort_by_key(|anchor| anchor.mac_address.to_string());
+        devices.sort_by_key(|device| device.mac_address.to_string());
+
+        Scene {
+            anchors,
+            devices,
+            obstacles: self
+                .obstacles
+                .iter()
+                .map(|(name, obstacle)| (name.clone(), *obstacle))
+                .collect(),
+            zones: self
+                .zones
+                .iter()
+                .map(|(name, (zone, _))| (name.clone(), *zone))
+                .collect(),
+        }
+    }
+
+    /// Capture the scene, the noise models, the antenna patterns
+    /// and the state of the connected UCI devices.
+    pub fn snapshot(&self) -> Snapshot {
+        println!("[_] Snapshot");
+
+        let mut uci_devices: Vec<DeviceSnapshot> = self
+            .devices
+            .values()
+            .map(|device| device.snapshot())
+            .collect();
+        let mut noise: Vec<NoiseSnapshot> = self
+            .noise
+            .iter()
+            .map(|(mac_address, noise)| NoiseSnapshot {
+                mac_address: *mac_address,
+                peer_mac_address: None,
+                model: noise.model(),
+            })
+            .chain(
+                self.link_noise
+                    .iter()
+                    .map(|((mac_address, peer_mac_address), noise)| NoiseSnapshot {
+                        mac_address: *mac_address,
+                        peer_mac_address: Some(*peer_mac_address),
+                        model: noise.model(),
+                    }),
+            )
+            .collect();
+        let mut antennas: Vec<AntennaSnapshot> = self
+            .antennas
+            .iter()
+            .map(|(mac_address, pattern)| AntennaSnapshot {
+                mac_address: *mac_address,
+                pattern: *pattern,
+            })
+            .collect();
+        uci_devices.sort_by_key(|device| device.mac_address.to_string());
+        noise.sort_by_key(|noise| {
+            (
+                noise.mac_address.to_string(),
+                noise
+                    .peer_mac_address
+                    .map(|mac_address| mac_address.to_string()),
+            )
+        });
+        antennas.sort_by_key(|antenna| antenna.mac_address.to_string());
+
+        Snapshot {
+            scene: self.save_scene(),
+            uci_devices,
+            noise,
+            antennas,
+        }
+    }
+
+    /// Restore a state captured with `snapshot`. Connected UCI devices
+    /// missing from the snapshot are reset, and devices of the snapshot
+    /// which are not connected are ignored. The state is left unchanged
+    /// if the snapshot is invalid.
+    pub fn restore(&mut self, snapshot: Snapshot) -> PicaCommandStatus {
+        println!("[_] Restore");
+
+        let mut restored = Vec::new();
+        for (device_handle, device) in self.devices.iter() {
+            let device_snapshot = snapshot
+                .uci_devices
+                .iter()
+                .find(|snapshot| snapshot.mac_address == device.mac_address)
+                .cloned()
+                .unwrap_or_else(|| DeviceSnapshot::reset(device.mac_address));
+            restored.push((
+                *device_handle,
+                device
+                    .prepare_restore(&device_snapshot)
+                    .map_err(PicaCommandError::InvalidSnapshot)?,
+            ));
+        }
+
+        self.load_scene(snapshot.scene)?;
+
+        self.noise.clear();
+        self.link_noise.clear();
+        for noise in snapshot.noise {
+            match noise.peer_mac_address {
+                Some(peer_mac_address) => self.link_noise.insert(
+                    (noise.mac_address, peer_mac_address),
+                    Noise::new(noise.model),
+                ),
+                None => self
+                    .noise
+                    .insert(noise.mac_address, Noise::new(noise.model)),
+            };
+        }
+        self.antennas = snapshot
+            .antennas
+            .into_iter()
+            .map(|antenna| (antenna.mac_address, antenna.pattern))
+            .collect();
+
+        for (device_handle, restored) in restored {
+            self.get_device_mut(device_handle)
+                .unwrap()
+                .restore(restored);
+        }
+        Ok(())
+    }
+
+    /// Advance the simulation clock by a number of ranging intervals.
+    /// The shortest ranging interval of the active sessions and initiator
+    /// anchors is used, or the default ranging interval when none is active.
+    fn step_clock(&mut self, count: u32, pica_cmd_rsp_tx: oneshot::Sender<PicaCommandStatus>) {
+        let ranging_interval = self
+            .devices
+            .values()
+            .flat_map(|device| device.get_active_ranging_intervals())
+            .chain(
+                self.anchors
+                    .values()
+                    .filter_map(|anchor| anchor.get_active_ranging_interval()),
+            )
+            .min()
+            .unwrap_or(session::DEFAULT_RANGING_INTERVAL);
+
+        println!("[_] Step clock");
+        println!("  count: {}", count);
+        println!("  ranging_interval: {:?}", ranging_interval);
+
+        self.clock.advance(ranging_interval * count);
+
+        pica_cmd_rsp_tx.send(Ok(())).unwrap_or_else(|err| {
+            println!("Failed to send step-clock command response: {:?}", err)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn restore_malformed_app_config() {
+        let (event_tx, _) = broadcast::channel(16);
+        let mut pica = Pica::new(event_tx, None);
+        let (_host, uwbs) = tokio::io::duplex(512);
+        pica.connect(uwbs, DeviceProfile::default(), None).await;
+        let mac_address = pica.devices.values().next().unwrap().mac_address;
+
+        let snapshot: Snapshot = serde_json::from_value(serde_json::json!({
+            "scene": {},
+            "uci_devices": [{
+                "mac_address": mac_address,
+                "state": 1,
+                "config": {},
+                "country_code": [0, 0],
+                "sessions": [{
+                    "id": 1,
+                    "session_type": 0,
+                    "state": 0,
+                    "sequence_number": 0,
+                    "app_config": { "9": [1] }
+                }]
+            }]
+        }))
+        .unwrap();
+        assert!(matches!(
+            pica.restore(snapshot),
+            Err(PicaCommandError::InvalidSnapshot(_))
+        ));
+        assert!(pica
+            .devices
+            .values()
+            .next()
+            .unwrap()
+            .get_session(1)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn virtual_device_ranging() {
+        let (event_tx, mut events) = broadcast::channel(64);
+        let mut pica = Pica::new(event_tx, None);
+        let (clock, tx) = (pica.clock(), pica.tx());
+        clock.pause();
+        tokio::spawn(async move { pica.run().await });
+
+        // Virtual devices are assigned the first free addresses.
+        let controlee = MacAddress::Short([0, 0]);
+        let controller = MacAddress::Short([0, 1]);
+        for (x, device_type, device_role, peer) in [
+            (0., DeviceType::Controlee, DeviceRole::Responder, controller),
+            (1., DeviceType::Controller, DeviceRole::Initiator, controlee),
+        ] {
+            let virtual_device = VirtualDevice {
+                position: Position::from_meters(x, 0., 0., 0., 0., 0.),
+                session: Some(VirtualSession {
+                    device_type,
+                    device_role,
+                    dst_mac_addresses: vec![peer],
+                    ..Default::default()
+                }),
+            };
+            let (rsp_tx, rsp_rx) = oneshot::channel();
+            tx.send(PicaCommand::CreateVirtualDevice(virtual_device, rsp_tx))
+                .await
+                .unwrap();
+            rsp_rx.await.unwrap().unwrap();
+        }
+
+        let mut active = HashSet::new();
+        while active.len() < 2 {
+            if let PicaEvent::VirtualDeviceNotification {
+                mac_address,
+                notification: DeviceNotification::SessionStatus { state: 2, .. },
+            } = events.recv().await.unwrap()
+            {
+                active.insert(mac_address);
+            }
+        }
+
+        let (rsp_tx, rsp_rx) = oneshot::channel();
+        tx.send(PicaCommand::StepClock(1, rsp_tx)).await.unwrap();
+        rsp_rx.await.unwrap().unwrap();
+
+        let mut ranged = HashMap::new();
+        while ranged.len() < 2 {
+            if let PicaEvent::VirtualDeviceNotification {
+                mac_address,
+                notification: DeviceNotification::RangeData { measurements, .. },
+            } = events.recv().await.unwrap()
+            {
+                if !measurements.is_empty() {
+                    ranged.insert(mac_address, measurements);
+                }
+            }
+        }
+        for (mac_address, peer) in [(controller, controlee), (controlee, controller)] {
+            let measurements = &ranged[&mac_address];
+            assert_eq!(measurements.len(), 1);
+            assert_eq!(measurements[0].mac_address, peer);
+            assert_eq!(measurements[0].status, 0);
+            assert_eq!(measurements[0].distance, 100);
+        }
+    }
+
+    /// Start the simulator with a paused clock, and a reconnect grace
+    /// period of one second.
+    fn start_pica() -> (
+        Clock,
+        mpsc::Sender<PicaCommand>,
+        broadcast::Receiver<PicaEvent>,
+    ) {
+        let (event_tx, events) = broadcast::channel(64);
+        let mut pica = Pica::new(event_tx, None);
+        pica.set_reconnect_grace_period(Duration::from_secs(1), true);
+        let (clock, tx) = (pica.clock(), pica.tx());
+        clock.pause();
+        tokio::spawn(async move { pica.run().await });
+        (clock, tx, events)
+    }
+
+    /// Read the packets sent to the host, until the expected packet.
+    async fn read_until(
+        host: &mut tokio::io::DuplexStream,
+        expected: impl Fn(&[u8]) -> bool,
+    ) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        loop {
+            let mut packet = vec![0; HEADER_SIZE];
+            host.read_exact(&mut packet).await.unwrap();
+            packet.resize(HEADER_SIZE + packet[3] as usize, 0);
+            host.read_exact(&mut packet[HEADER_SIZE..]).await.unwrap();
+            let found = expected(&packet);
+            packets.push(packet);
+            if found {
+                return packets;
+            }
+        }
+    }
+
+    /// Connect a host selecting its client identifier, return the packets
+    /// received until the response.
+    async fn connect_host(
+        tx: &mpsc::Sender<PicaCommand>,
+        client_id: &str,
+    ) -> (tokio::io::DuplexStream, Vec<Vec<u8>>) {
+        let (mut host, uwbs) = tokio::io::duplex(1024);
+        tx.send(PicaCommand::Connect(
+            Box::new(uwbs),
+            DeviceProfile::default(),
+            None,
+        ))
+        .await
+        .unwrap();
+        let cmd = UciCommandBuilder {
+            group_id: GroupId::VendorReservedA,
+            opcode: PICA_SET_CLIENT_ID,
+            payload: Some(client_id.as_bytes().to_vec().into()),
+        }
+        .build();
+        host.write_all(&cmd.to_bytes()).await.unwrap();
+        let packets = read_until(&mut host, |packet| packet[..2] == [0x4a, 0x00]).await;
+        assert_eq!(packets.last().unwrap()[4], 0);
+        (host, packets)
+    }
+
+    async fn get_uci_devices(tx: &mpsc::Sender<PicaCommand>) -> Vec<DeviceInfo> {
+        let (rsp_tx, rsp_rx) = oneshot::channel();
+        tx.send(PicaCommand::GetUciDevices(rsp_tx)).await.unwrap();
+        rsp_rx.await.unwrap()
+    }
+
+    async fn wait_event(
+        events: &mut broadcast::Receiver<PicaEvent>,
+        expected: impl Fn(&PicaEvent) -> bool,
+    ) {
+        while !expected(&events.recv().await.unwrap()) {}
+    }
+
+    #[tokio::test]
+    async fn reconnect_resumes_sessions() {
+        let (_clock, tx, mut events) = start_pica();
+        let (mut host, _) = connect_host(&tx, "a").await;
+        let cmd = SessionInitCmdBuilder {
+            session_id: 1,
+            session_type: SessionType::FiraRangingSession,
+        }
+        .build();
+        host.write_all(&cmd.to_bytes()).await.unwrap();
+        read_until(&mut host, |packet| packet[..2] == [0x41, 0x00]).await;
+        drop(host);
+        wait_event(&mut events, |event| {
+            matches!(event, PicaEvent::DeviceDisconnected { .. })
+        })
+        .await;
+        assert!(!get_uci_devices(&tx).await[0].connected);
+
+        // The waiting device is attached to the new connection, and
+        // reports the state of its session.
+        let session_status =
+            |packet: &[u8]| packet[..2] == [0x61, 0x02] && packet[4..8] == [1, 0, 0, 0];
+        let (mut host, packets) = connect_host(&tx, "a").await;
+        if !packets.iter().any(|packet| session_status(packet)) {
+            read_until(&mut host, session_status).await;
+        }
+        wait_event(&mut events, |event| {
+            matches!(event, PicaEvent::DeviceReconnected { .. })
+        })
+        .await;
+
+        let devices = get_uci_devices(&tx).await;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].mac_address, MacAddress::Short([0, 0]));
+        assert!(devices[0].connected);
+        assert_eq!(devices[0].sessions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn disconnected_device_expiry() {
+        let (clock, tx, mut events) = start_pica();
+        let (host, _) = connect_host(&tx, "a").await;
+        drop(host);
+        wait_event(&mut events, |event| {
+            matches!(event, PicaEvent::DeviceDisconnected { .. })
+        })
+        .await;
+
+        clock.advance(Duration::from_secs(2));
+        wait_event(&mut events, |event| {
+            matches!(event, PicaEvent::DeviceRemoved { .. })
+        })
+        .await;
+        assert!(get_uci_devices(&tx).await.is_empty());
+    }
+
+    async fn device_command(
+        tx: &mpsc::Sender<PicaCommand>,
+        cmd: fn(MacAddress, oneshot::Sender<PicaCommandStatus>) -> PicaCommand,
+        mac_address: MacAddress,
+    ) -> PicaCommandStatus {
+        let (rsp_tx, rsp_rx) = oneshot::channel();
+        tx.send(cmd(mac_address, rsp_tx)).await.unwrap();
+        rsp_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn uci_device_commands() {
+        let (_clock, tx, mut events) = start_pica();
+        let (mut host, _) = connect_host(&tx, "a").await;
+        let mac_address = MacAddress::Short([0, 0]);
+        let unknown = MacAddress::Short([0, 1]);
+
+        assert_eq!(
+            device_command(&tx, PicaCommand::SetUciDeviceError, mac_address).await,
+            Ok(())
+        );
+        read_until(&mut host, |packet| packet == [0x60, 0x01, 0x00, 0x01, 0xff]).await;
+        assert_eq!(
+            device_command(&tx, PicaCommand::ResetUciDevice, mac_address).await,
+            Ok(())
+        );
+        read_until(&mut host, |packet| packet == [0x60, 0x01, 0x00, 0x01, 0x01]).await;
+        assert_eq!(
+            device_command(&tx, PicaCommand::DisconnectUciDevice, mac_address).await,
+            Ok(())
+        );
+        assert_eq!(host.read(&mut [0; 1]).await.unwrap(), 0);
+        wait_event(&mut events, |event| {
+            matches!(event, PicaEvent::DeviceDisconnected { .. })
+        })
+        .await;
+
+        // The device waits for its host to reconnect.
+        for cmd in [
+            PicaCommand::DisconnectUciDevice,
+            PicaCommand::ResetUciDevice,
+            PicaCommand::SetUciDeviceError,
+        ] {
+            assert_eq!(
+                device_command(&tx, cmd, mac_address).await,
+                Err(PicaCommandError::DeviceNotConnected(mac_address))
+            );
+            assert_eq!(
+                device_command(&tx, cmd, unknown).await,
+                Err(PicaCommandError::DeviceNotFound(unknown))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn notification_events() {
+        let (_clock, tx, mut events) = start_pica();
+        let anchor = MacAddress::Short([0, 5]);
+        let (rsp_tx, rsp_rx) = oneshot::channel();
+        tx.send(PicaCommand::CreateAnchor(
+            anchor,
+            Position::from_meters(1., 0., 0., 0., 0., 0.),
+            rsp_tx,
+        ))
+        .await
+        .unwrap();
+        rsp_rx.await.unwrap().unwrap();
+
+        // Start a ranging session with the anchor.
+        let (mut host, _) = connect_host(&tx, "a").await;
+        let mac_address = MacAddress::Short([0, 0]);
+        let session = VirtualSession {
+            dst_mac_addresses: vec![anchor],
+            ..Default::default()
+        };
+        for cmd in session.commands(mac_address) {
+            host.write_all(&cmd.to_bytes()).await.unwrap();
+            read_until(&mut host, |packet| packet[0] >> 5 == 2).await;
+        }
+        wait_event(&mut events, |event| {
+            matches!(
+                event,
+                PicaEvent::SessionStateChanged { mac_address: m, session_id: 1, state: 2, .. }
+                    if *m == mac_address
+            )
+        })
+        .await;
+
+        let (rsp_tx, rsp_rx) = oneshot::channel();
+        tx.send(PicaCommand::StepClock(1, rsp_tx)).await.unwrap();
+        rsp_rx.await.unwrap().unwrap();
+        read_until(&mut host, |packet| packet[..2] == [0x62, 0x00]).await;
+        wait_event(&mut events, |event| {
+            matches!(
+                event,
+                PicaEvent::RangeData { mac_address: m, session_id: 1, measurements, .. }
+                    if *m == mac_address
+                        && measurements.len() == 1
+                        && measurements[0].mac_address == anchor
+                        && measurements[0].distance == 100
+            )
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn notification_events_lossless() {
+        let (event_tx, mut events) = broadcast::channel(256);
+        let mut pica = Pica::new(event_tx, None);
+        let (mut host, uwbs) = tokio::io::duplex(1024);
+        pica.connect(uwbs, DeviceProfile::default(), None).await;
+        let device_tx = pica.devices.values().next().unwrap().tx.clone();
+
+        // Flood the command channel before Pica runs.
+        let tx = pica.tx();
+        while tx
+            .try_send(PicaCommand::GetClock(oneshot::channel().0))
+            .is_ok()
+        {}
+
+        let count: u32 = 64;
+        let host = tokio::spawn(async move {
+            read_until(&mut host, |packet| {
+                packet[..2] == [0x62, 0x00] && packet[4..8] == (count - 1).to_le_bytes()
+            })
+            .await
+        });
+        for sequence_number in 0..count {
+            let ntf = ShortMacTwoWayRangeDataNtfBuilder {
+                sequence_number,
+                session_id: 1,
+                rcr_indicator: 0,
+                current_ranging_interval: 0,
+                two_way_ranging_measurements: vec![],
+            };
+            device_tx.send(ntf.build().into()).await.unwrap();
+        }
+        host.await.unwrap();
+
+        tokio::spawn(async move { pica.run().await });
+        for expected in 0..count {
+            loop {
+                let event = tokio::time::timeout(Duration::from_secs(1), events.recv());
+                if let PicaEvent::RangeData {
+                    sequence_number, ..
+                } = event.await.expect("missing range-data event").unwrap()
+                {
+                    assert_eq!(sequence_number, expected);
+                    break;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn uci_device_error_stops_ranging() {
+        let (_clock, tx, _events) = start_pica();
+        let anchor = MacAddress::Short([0, 5]);
+        let (rsp_tx, rsp_rx) = oneshot::channel();
+        tx.send(PicaCommand::CreateAnchor(
+            anchor,
+            Position::default(),
+            rsp_tx,
+        ))
+        .await
+        .unwrap();
+        rsp_rx.await.unwrap().unwrap();
+
+        let (mut host, _) = connect_host(&tx, "a").await;
+        let mac_address = MacAddress::Short([0, 0]);
+        let session = VirtualSession {
+            dst_mac_addresses: vec![anchor],
+            ..Default::default()
+        };
+        for cmd in session.commands(mac_address) {
+            host.write_all(&cmd.to_bytes()).await.unwrap();
+            read_until(&mut host, |packet| packet[0] >> 5 == 2).await;
+        }
+        let step_clock = || async {
+            let (rsp_tx, rsp_rx) = oneshot::channel();
+            tx.send(PicaCommand::StepClock(1, rsp_tx)).await.unwrap();
+            rsp_rx.await.unwrap().unwrap();
+        };
+        step_clock().await;
+        read_until(&mut host, |packet| packet[..2] == [0x62, 0x00]).await;
+
+        assert_eq!(
+            device_command(&tx, PicaCommand::SetUciDeviceError, mac_address).await,
+            Ok(())
+        );
+        read_until(&mut host, |packet| packet == [0x60, 0x01, 0x00, 0x01, 0xff]).await;
+        for _ in 0..3 {
+            step_clock().await;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // No range data is received before the response to the next
+        // command, and the session is back to the idle state.
+        let cmd = SessionGetStateCmdBuilder { session_id: 1 }.build();
+        host.write_all(&cmd.to_bytes()).await.unwrap();
+        let packets = read_until(&mut host, |packet| packet[..2] == [0x41, 0x06]).await;
+        assert!(packets.iter().all(|packet| packet[..2] != [0x62, 0x00]));
+        assert_eq!(packets.last().unwrap()[4..], [0x00, 0x03]);
+    }
+
+    #[tokio::test]
+    async fn connection_framing() {
+        let (mut host, uwbs) = tokio::io::duplex(512);
+        let mut connection = Connection::new(uwbs, None);
+
+        // Packet split over two writes, followed by two merged packets.
+        host.write_all(&[0x20, 0x02]).await.unwrap();
+        host.write_all(&[0x00, 0x00]).await.unwrap();
+        host.write_all(&[0x21, 0x00, 0x00, 0x01, 0x2a, 0x20, 0x03, 0x00, 0x00])
+            .await
+            .unwrap();
+        // Data packet, with a two octets payload length.
+        let mut data = vec![0x01, 0x00, 0x00, 0x01];
+        data.resize(4 + 256, 0x55);
+        host.write_all(&data).await.unwrap();
+        drop(host);
+
+        let mut packets = Vec::new();
+        while let Some(packet) = connection.read().await.unwrap() {
+            packets.push(packet.to_vec());
+        }
+        assert_eq!(
+            packets,
+            vec![
+                vec![0x20, 0x02, 0x00, 0x00],
+                vec![0x21, 0x00, 0x00, 0x01, 0x2a],
+                vec![0x20, 0x03, 0x00, 0x00],
+                data
+            ]
+        );
+    }
 }
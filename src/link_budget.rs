@@ -0,0 +1,113 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Link budget of UWB exchanges, using the free space path loss model.
+
+use crate::session::ChannelNumber;
+
+/// Transmit power, in dBm: maximum mean power spectral density of
+/// -41.3 dBm/MHz over the 500 MHz bandwidth of the channel.
+const TX_POWER: f32 = -14.3;
+/// Additional transmit power of the payload when the adaptive payload
+/// power is enabled, in dB.
+const ADAPTIVE_PAYLOAD_POWER_GAIN: f32 = 3.;
+/// Minimum received power for a successful ranging exchange, in dBm.
+pub const RX_SENSITIVITY: f32 = -100.;
+/// Distance under which the path loss is considered constant, in m.
+const MIN_DISTANCE: f32 = 0.01;
+
+/// Center frequency of the channel, in MHz.
+fn center_frequency(channel_number: ChannelNumber) -> f32 {
+    match channel_number {
+        ChannelNumber::ChannelNumber5 => 6489.6,
+        ChannelNumber::ChannelNumber6 => 6988.8,
+        ChannelNumber::ChannelNumber8 => 7488.0,
+        ChannelNumber::ChannelNumber9 => 7987.2,
+        ChannelNumber::ChannelNumber10 => 8486.4,
+        ChannelNumber::ChannelNumber12 => 8985.6,
+        ChannelNumber::ChannelNumber13 => 9484.8,
+        ChannelNumber::ChannelNumber14 => 9984.0,
+    }
+}
+
+/// Free space path loss at the given distance in m, in dB.
+fn path_loss(distance: f32, channel_number: ChannelNumber) -> f32 {
+    let distance = distance.max(MIN_DISTANCE);
+    // FSPL = 20 log10(d) + 20 log10(f) - 27.55, with d in m and f in MHz.
+    (20. * distance.log10() + 20. * center_frequency(channel_number).log10() - 27.55).max(0.)
+}
+
+/// Radio parameters of a ranging exchange.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkBudget {
+    pub channel_number: ChannelNumber,
+    pub tx_adaptive_payload_power: bool,
+}
+
+impl LinkBudget {
+    /// Received signal strength at the given distance in m,
+    /// after crossing obstacles with the given attenuation in dB.
+    pub fn rssi(&self, distance: f32, attenuation: f32) -> f32 {
+        let tx_power = if self.tx_adaptive_payload_power {
+            TX_POWER + ADAPTIVE_PAYLOAD_POWER_GAIN
+        } else {
+            TX_POWER
+        };
+        tx_power - path_loss(distance, self.channel_number) - attenuation
+    }
+
+    /// Return true if the received signal strength is high enough
+    /// for the ranging exchange to succeed.
+    pub fn in_range(rssi: f32) -> bool {
+        rssi >= RX_SENSITIVITY
+    }
+}
+
+impl Default for LinkBudget {
+    fn default() -> Self {
+        LinkBudget {
+            channel_number: ChannelNumber::ChannelNumber9,
+            tx_adaptive_payload_power: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range() {
+        let link_budget = LinkBudget::default();
+        assert!(LinkBudget::in_range(link_budget.rssi(10., 0.)));
+        assert!(LinkBudget::in_range(link_budget.rssi(50., 0.)));
+        assert!(!LinkBudget::in_range(link_budget.rssi(100., 0.)));
+        assert!(!LinkBudget::in_range(link_budget.rssi(10., 50.)));
+    }
+
+    #[test]
+    fn channel_and_power() {
+        let channel_5 = LinkBudget {
+            channel_number: ChannelNumber::ChannelNumber5,
+            tx_adaptive_payload_power: false,
+        };
+        let channel_9 = LinkBudget::default();
+        let boosted = LinkBudget {
+            tx_adaptive_payload_power: true,
+            ..Default::default()
+        };
+        assert!(channel_5.rssi(10., 0.) > channel_9.rssi(10., 0.));
+        assert!(boosted.rssi(10., 0.) > channel_9.rssi(10., 0.));
+    }
+}
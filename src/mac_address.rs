@@ -97,6 +97,56 @@ impl Display for MacAddress {
     }
 }
 
+/// Range of short MAC addresses, bounds included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacAddressRange {
+    pub first: MacAddress,
+    pub last: MacAddress,
+}
+
+impl MacAddressRange {
+    fn bounds(&self) -> Option<(u16, u16)> {
+        match (self.first, self.last) {
+            (MacAddress::Short(first), MacAddress::Short(last)) => {
+                Some((u16::from_be_bytes(first), u16::from_be_bytes(last)))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self.bounds() {
+            Some((first, last)) if first <= last => Ok(()),
+            Some(_) => Err(format!("empty range {}-{}", self.first, self.last)),
+            None => Err("only short MAC addresses are supported".to_string()),
+        }
+    }
+
+    pub fn contains(&self, mac_address: &MacAddress) -> bool {
+        match (self.bounds(), mac_address) {
+            (Some((first, last)), MacAddress::Short(address)) => {
+                (first..=last).contains(&u16::from_be_bytes(*address))
+            }
+            _ => false,
+        }
+    }
+
+    /// Addresses of the range, in increasing order.
+    pub fn addresses(&self) -> impl Iterator<Item = MacAddress> {
+        let (first, last) = self.bounds().unwrap_or((1, 0));
+        (first..=last).map(|address| MacAddress::Short(address.to_be_bytes()))
+    }
+}
+
+impl Default for MacAddressRange {
+    fn default() -> Self {
+        MacAddressRange {
+            first: MacAddress::Short([0x00, 0x00]),
+            last: MacAddress::Short([0xff, 0xff]),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,4 +193,31 @@ mod tests {
         );
         assert_eq!(extend_mac_address.to_string(), extend_mac_address);
     }
+
+    #[test]
+    fn mac_address_range() {
+        let range = MacAddressRange {
+            first: MacAddress::Short([0xfe, 0xfe]),
+            last: MacAddress::Short([0xff, 0x00]),
+        };
+        assert!(range.validate().is_ok());
+        assert!(range.contains(&MacAddress::Short([0xfe, 0xff])));
+        assert!(!range.contains(&MacAddress::Short([0xff, 0x01])));
+        assert!(!range.contains(&MacAddress::Extend([0xff; 8])));
+        assert_eq!(
+            range.addresses().collect::<Vec<_>>(),
+            vec![
+                MacAddress::Short([0xfe, 0xfe]),
+                MacAddress::Short([0xfe, 0xff]),
+                MacAddress::Short([0xff, 0x00])
+            ]
+        );
+
+        let range = MacAddressRange {
+            first: range.last,
+            last: range.first,
+        };
+        assert!(range.validate().is_err());
+        assert_eq!(range.addresses().count(), 0);
+    }
 }
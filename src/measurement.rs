@@ -0,0 +1,136 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::position::Position;
+use crate::uci_packets::{ShortAddressTwoWayRangingMeasurement, StatusCode};
+
+/// Highest figure of merit reported for angle of arrival measurements.
+pub const MAX_FOM: u8 = 100;
+
+/// Angle of arrival measured by one side of a ranging exchange.
+#[derive(Debug, Clone, Copy)]
+pub struct Aoa {
+    /// Azimuth in degrees, in the range [-180, 180].
+    pub azimuth: i16,
+    pub azimuth_fom: u8,
+    /// Elevation in degrees, in the range [-90, 90].
+    pub elevation: i8,
+    pub elevation_fom: u8,
+}
+
+/// Simulated result of a two-way ranging exchange between
+/// a local device and a remote device.
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    pub status: StatusCode,
+    /// Set when the line of sight between the devices is obstructed.
+    pub nlos: bool,
+    /// Distance in cm.
+    pub distance: u16,
+    /// Angle of arrival of the remote device, seen from the local device.
+    pub local: Aoa,
+    /// Angle of arrival of the local device, seen from the remote device.
+    pub remote: Aoa,
+    /// Received signal strength in dBm, null when unknown.
+    pub rssi: f32,
+}
+
+impl Measurement {
+    /// Compute the exact measurement from the geometry of the scene.
+    pub fn new(local: &Position, remote: &Position) -> Self {
+        let (distance, local_azimuth, local_elevation) =
+            local.compute_range_azimuth_elevation(remote);
+        let (remote_distance, remote_azimuth, remote_elevation) =
+            remote.compute_range_azimuth_elevation(local);
+
+        assert!(distance == remote_distance);
+
+        Measurement {
+            status: StatusCode::UciStatusOk,
+            nlos: false,
+            distance,
+            local: Aoa {
+                azimuth: local_azimuth,
+                azimuth_fom: MAX_FOM,
+                elevation: local_elevation,
+                elevation_fom: MAX_FOM,
+            },
+            remote: Aoa {
+                azimuth: remote_azimuth,
+                azimuth_fom: MAX_FOM,
+                elevation: remote_elevation,
+                elevation_fom: MAX_FOM,
+            },
+            rssi: 0.,
+        }
+    }
+
+    /// Measurement reported when the ranging exchange failed.
+    pub fn failed(status: StatusCode) -> Self {
+        let aoa = Aoa {
+            azimuth: 0,
+            azimuth_fom: 0,
+            elevation: 0,
+            elevation_fom: 0,
+        };
+        Measurement {
+            status,
+            nlos: false,
+            distance: 0,
+            local: aoa,
+            remote: aoa,
+            rssi: 0.,
+        }
+    }
+
+    /// Measurement seen from the remote device.
+    pub fn reversed(self) -> Self {
+        Measurement {
+            local: self.remote,
+            remote: self.local,
+            ..self
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == StatusCode::UciStatusOk
+    }
+
+    /// Received signal strength reported in UCI 2.0 range data
+    /// notifications: absolute value of the RSSI in dBm, in Q7.1 format.
+    pub fn uci_rssi(&self) -> u8 {
+        (-self.rssi * 2.).round().clamp(0., u8::MAX as f32) as u8
+    }
+
+    pub fn to_short_address_measurement(
+        self,
+        mac_address: u16,
+    ) -> ShortAddressTwoWayRangingMeasurement {
+        ShortAddressTwoWayRangingMeasurement {
+            mac_address,
+            status: self.status,
+            nlos: self.nlos as u8,
+            distance: self.distance,
+            aoa_azimuth: self.local.azimuth as u16,
+            aoa_azimuth_fom: self.local.azimuth_fom,
+            aoa_elevation: self.local.elevation as u16,
+            aoa_elevation_fom: self.local.elevation_fom,
+            aoa_destination_azimuth: self.remote.azimuth as u16,
+            aoa_destination_azimuth_fom: self.remote.azimuth_fom,
+            aoa_destination_elevation: self.remote.elevation as u16,
+            aoa_destination_elevation_fom: self.remote.elevation_fom,
+            slot_index: 0,
+        }
+    }
+}
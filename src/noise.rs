@@ -0,0 +1,204 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::measurement::{Aoa, Measurement, MAX_FOM};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+/// Figure of merit lost for every degree of error injected
+/// into an angle of arrival measurement.
+const AOA_FOM_LOSS_PER_DEGREE: f32 = 2.;
+
+/// Parameters of the error injected into ranging measurements.
+/// The default model is noiseless.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseModel {
+    /// Standard deviation of the gaussian range error, in cm.
+    pub range_std_dev: f32,
+    /// Standard deviation of the gaussian AoA error at boresight, in degrees.
+    pub aoa_std_dev: f32,
+    /// Increase of the AoA standard deviation for every degree
+    /// away from boresight.
+    pub aoa_std_dev_per_degree: f32,
+    /// Probability of a measurement being an outlier, in the range [0, 1].
+    /// Outliers get an additional range error uniformly distributed
+    /// in [0, outlier_range] and a random angle of arrival.
+    pub outlier_probability: f32,
+    /// Maximum range error of outliers, in cm.
+    pub outlier_range: f32,
+    /// Seed of the random number generator. Two models with the same
+    /// parameters and seed produce the same sequence of errors.
+    pub seed: u64,
+}
+
+/// Noise source generating errors according to a [`NoiseModel`].
+pub struct Noise {
+    model: NoiseModel,
+    rng: StdRng,
+}
+
+fn gaussian(rng: &mut StdRng, std_dev: f32) -> f32 {
+    // Normal::new only fails for non finite standard deviations.
+    Normal::new(0., std_dev.abs())
+        .map(|normal| normal.sample(rng))
+        .unwrap_or(0.)
+}
+
+fn wrap_azimuth(azimuth: f32) -> f32 {
+    let azimuth = (azimuth + 180.).rem_euclid(360.) - 180.;
+    if azimuth == -180. {
+        180.
+    } else {
+        azimuth
+    }
+}
+
+fn aoa_fom(error: f32) -> u8 {
+    (MAX_FOM as f32 - AOA_FOM_LOSS_PER_DEGREE * error.abs())
+        .round()
+        .clamp(0., MAX_FOM as f32) as u8
+}
+
+impl Noise {
+    pub fn new(model: NoiseModel) -> Self {
+        Noise {
+            model,
+            rng: StdRng::seed_from_u64(model.seed),
+        }
+    }
+
+    pub fn model(&self) -> NoiseModel {
+        self.model
+    }
+
+    fn aoa_std_dev(&self, angle: f32) -> f32 {
+        self.model.aoa_std_dev + self.model.aoa_std_dev_per_degree * angle.abs()
+    }
+
+    fn apply_aoa(&mut self, aoa: Aoa, outlier: bool) -> Aoa {
+        let azimuth = aoa.azimuth as f32;
+        let elevation = aoa.elevation as f32;
+        let (noisy_azimuth, noisy_elevation) = if outlier {
+            (
+                self.rng.gen_range(-180. ..=180.),
+                self.rng.gen_range(-90. ..=90.),
+            )
+        } else {
+            let azimuth_std_dev = self.aoa_std_dev(azimuth);
+            let elevation_std_dev = self.aoa_std_dev(elevation);
+            (
+                wrap_azimuth(azimuth + gaussian(&mut self.rng, azimuth_std_dev)),
+                (elevation + gaussian(&mut self.rng, elevation_std_dev)).clamp(-90., 90.),
+            )
+        };
+        let azimuth_error = wrap_azimuth(noisy_azimuth - azimuth);
+        let elevation_error = noisy_elevation - elevation;
+
+        Aoa {
+            azimuth: noisy_azimuth.round() as i16,
+            azimuth_fom: aoa.azimuth_fom.min(aoa_fom(azimuth_error)),
+            elevation: noisy_elevation.round() as i8,
+            elevation_fom: aoa.elevation_fom.min(aoa_fom(elevation_error)),
+        }
+    }
+
+    /// Inject errors into the measurement. The figures of merit of the
+    /// angles of arrival are lowered according to the injected error.
+    pub fn apply(&mut self, measurement: Measurement) -> Measurement {
+        if !measurement.is_ok() {
+            return measurement;
+        }
+
+        let outlier = self.model.outlier_probability > 0.
+            && self
+                .rng
+                .gen_bool(self.model.outlier_probability.min(1.) as f64);
+
+        let mut distance =
+            measurement.distance as f32 + gaussian(&mut self.rng, self.model.range_std_dev);
+        if outlier && self.model.outlier_range > 0. {
+            distance += self.rng.gen_range(0. ..=self.model.outlier_range);
+        }
+
+        Measurement {
+            distance: distance.round().clamp(0., u16::MAX as f32) as u16,
+            local: self.apply_aoa(measurement.local, outlier),
+            remote: self.apply_aoa(measurement.remote, outlier),
+            ..measurement
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::Position;
+
+    fn measurement() -> Measurement {
+        Measurement::new(
+            &Position::new(0, 0, 0, 0, 0, 0),
+            &Position::new(100, 0, 100, 0, 0, 0),
+        )
+    }
+
+    #[test]
+    fn noiseless() {
+        let mut noise = Noise::new(NoiseModel::default());
+        let exact = measurement();
+        let noisy = noise.apply(exact);
+        assert_eq!(noisy.distance, exact.distance);
+        assert_eq!(noisy.local.azimuth, exact.local.azimuth);
+        assert_eq!(noisy.local.azimuth_fom, MAX_FOM);
+        assert_eq!(noisy.remote.elevation, exact.remote.elevation);
+    }
+
+    #[test]
+    fn deterministic() {
+        let model = NoiseModel {
+            range_std_dev: 10.,
+            aoa_std_dev: 5.,
+            aoa_std_dev_per_degree: 0.1,
+            outlier_probability: 0.1,
+            outlier_range: 500.,
+            seed: 42,
+        };
+        let mut noise_a = Noise::new(model);
+        let mut noise_b = Noise::new(model);
+        for _ in 0..100 {
+            let a = noise_a.apply(measurement());
+            let b = noise_b.apply(measurement());
+            assert_eq!(a.distance, b.distance);
+            assert_eq!(a.local.azimuth, b.local.azimuth);
+            assert_eq!(a.local.azimuth_fom, b.local.azimuth_fom);
+        }
+    }
+
+    #[test]
+    fn fom_reflects_error() {
+        let mut noise = Noise::new(NoiseModel {
+            aoa_std_dev: 20.,
+            ..Default::default()
+        });
+        let exact = measurement();
+        for _ in 0..100 {
+            let noisy = noise.apply(exact);
+            let error = wrap_azimuth((noisy.local.azimuth - exact.local.azimuth) as f32);
+            assert!(noisy.local.azimuth_fom <= aoa_fom((error.abs() - 1.).max(0.)));
+            assert!((-90..=90).contains(&noisy.local.elevation));
+        }
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::measurement::{Aoa, Measurement};
+use crate::position::{Point, Position};
+use crate::uci_packets::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Default range bias introduced by an obstacle, in cm.
+const DEFAULT_RANGE_BIAS: f32 = 30.;
+/// Total attenuation above which the ranging exchange fails, in dB.
+const MAX_ATTENUATION: f32 = 30.;
+/// Figure of merit lost for every dB of attenuation.
+const FOM_LOSS_PER_DB: f32 = 2.;
+
+fn default_range_bias() -> f32 {
+    DEFAULT_RANGE_BIAS
+}
+
+/// Static axis aligned box placed in the scene, e.g. a wall or a piece
+/// of furniture. Measurements between two devices are in Non Line Of
+/// Sight when the segment between them crosses the obstacle.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    /// Corner of the box with the lowest coordinates.
+    pub min: Point,
+    /// Corner of the box with the highest coordinates.
+    pub max: Point,
+    /// Attenuation of the signal crossing the obstacle, in dB.
+    pub attenuation: f32,
+    /// Positive bias added to the range measured through the obstacle, in cm.
+    #[serde(default = "default_range_bias")]
+    pub range_bias: f32,
+}
+
+impl Obstacle {
+    /// Return true if the segment between the two positions crosses
+    /// the obstacle. Uses the slab method: the segment is clipped
+    /// against the three pairs of planes bounding the box.
+    pub fn intersects(&self, from: &Position, to: &Position) -> bool {
+        let from = from.point();
+        let delta = to.point() - from;
+        let min = self.min.to_meters().min(self.max.to_meters());
+        let max = self.min.to_meters().max(self.max.to_meters());
+
+        let mut t_enter: f32 = 0.;
+        let mut t_exit: f32 = 1.;
+        for axis in 0..3 {
+            if delta[axis] == 0. {
+                if from[axis] < min[axis] || from[axis] > max[axis] {
+                    return false;
+                }
+            } else {
+                let t0 = (min[axis] - from[axis]) / delta[axis];
+                let t1 = (max[axis] - from[axis]) / delta[axis];
+                t_enter = t_enter.max(t0.min(t1));
+                t_exit = t_exit.min(t0.max(t1));
+                if t_enter > t_exit {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+fn obstruct_aoa(aoa: Aoa, attenuation: f32) -> Aoa {
+    let fom_loss = (FOM_LOSS_PER_DB * attenuation)
+        .round()
+        .clamp(0., u8::MAX as f32) as u8;
+    Aoa {
+        azimuth_fom: aoa.azimuth_fom.saturating_sub(fom_loss),
+        elevation_fom: aoa.elevation_fom.saturating_sub(fom_loss),
+        ..aoa
+    }
+}
+
+/// Return the obstacles crossed by the segment between the two positions.
+pub fn crossed<'a>(
+    obstacles: impl IntoIterator<Item = &'a Obstacle>,
+    from: &Position,
+    to: &Position,
+) -> Vec<&'a Obstacle> {
+    obstacles
+        .into_iter()
+        .filter(|obstacle| obstacle.intersects(from, to))
+        .collect()
+}
+
+/// Total attenuation of the crossed obstacles, in dB.
+pub fn attenuation(crossed: &[&Obstacle]) -> f32 {
+    crossed.iter().map(|obstacle| obstacle.attenuation).sum()
+}
+
+/// Apply the effect of the crossed obstacles to the measurement:
+/// the measurement is marked as Non Line Of Sight, its range is
+/// biased and the figures of merit are lowered. The ranging exchange
+/// fails if the total attenuation is too high.
+pub fn obstruct(crossed: &[&Obstacle], measurement: Measurement) -> Measurement {
+    if crossed.is_empty() || !measurement.is_ok() {
+        return measurement;
+    }
+
+    let attenuation = attenuation(crossed);
+    let range_bias: f32 = crossed.iter().map(|obstacle| obstacle.range_bias).sum();
+
+    if attenuation > MAX_ATTENUATION {
+        return Measurement::failed(StatusCode::UciStatusRangingRxTimeout);
+    }
+
+    Measurement {
+        nlos: true,
+        distance: (measurement.distance as f32 + range_bias.max(0.))
+            .round()
+            .min(u16::MAX as f32) as u16,
+        local: obstruct_aoa(measurement.local, attenuation),
+        remote: obstruct_aoa(measurement.remote, attenuation),
+        ..measurement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wall() -> Obstacle {
+        Obstacle {
+            min: Point {
+                x: -100.,
+                y: -100.,
+                z: 50.,
+            },
+            max: Point {
+                x: 100.,
+                y: 100.,
+                z: 60.,
+            },
+            attenuation: 10.,
+            range_bias: DEFAULT_RANGE_BIAS,
+        }
+    }
+
+    #[test]
+    fn crossing() {
+        let a = Position::new(0, 0, 0, 0, 0, 0);
+        let b = Position::new(0, 0, 100, 0, 0, 0);
+        assert!(wall().intersects(&a, &b));
+        assert!(wall().intersects(&b, &a));
+    }
+
+    #[test]
+    fn not_crossing() {
+        let a = Position::new(0, 0, 0, 0, 0, 0);
+        // Both positions in front of the wall.
+        assert!(!wall().intersects(&a, &Position::new(0, 0, 40, 0, 0, 0)));
+        // Going around the wall.
+        assert!(!wall().intersects(&a, &Position::new(300, 0, 100, 0, 0, 0)));
+        // Parallel to the wall.
+        assert!(!wall().intersects(&a, &Position::new(200, 0, 0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn nlos_measurement() {
+        let a = Position::new(0, 0, 0, 0, 0, 0);
+        let b = Position::new(0, 0, 100, 0, 0, 0);
+        let wall = wall();
+        let crossed = crossed([&wall], &a, &b);
+        assert_eq!(attenuation(&crossed), wall.attenuation);
+
+        let measurement = obstruct(&crossed, Measurement::new(&a, &b));
+        assert!(measurement.is_ok());
+        assert!(measurement.nlos);
+        assert_eq!(measurement.distance, 100 + DEFAULT_RANGE_BIAS as u16);
+        assert!(measurement.local.azimuth_fom < 100);
+
+        let thick_wall = Obstacle {
+            attenuation: MAX_ATTENUATION + 1.,
+            ..wall
+        };
+        let measurement = obstruct(&[&thick_wall], Measurement::new(&a, &b));
+        assert!(!measurement.is_ok());
+    }
+}
@@ -14,13 +14,15 @@
 
 #![allow(clippy::unused_io_amount)]
 
+use crate::clock::Clock;
 use std::path::Path;
-use std::time::Instant;
+use std::time::Duration;
 use tokio::io::AsyncWriteExt;
 
 pub struct File {
     file: tokio::fs::File,
-    start_time: Instant,
+    clock: Clock,
+    start_time: Duration,
 }
 
 pub enum Direction {
@@ -29,7 +31,7 @@ pub enum Direction {
 }
 
 impl File {
-    pub async fn create<P: AsRef<Path>>(path: P) -> std::io::Result<File> {
+    pub async fn create<P: AsRef<Path>>(path: P, clock: Clock) -> std::io::Result<File> {
         let mut file = tokio::fs::File::create(path).await?;
 
         // PCAPng files must start with a Section Header Block.
@@ -50,16 +52,18 @@ impl File {
         file.write(&u32::to_le_bytes(0)).await?; // SnapLen (no limit)
         file.write(&u32::to_le_bytes(20)).await?; // Block Total Length
 
+        let start_time = clock.now();
         Ok(File {
             file,
-            start_time: Instant::now(),
+            clock,
+            start_time,
         })
     }
 
     pub async fn write(&mut self, packet: &[u8], _dir: Direction) -> std::io::Result<()> {
         let packet_data_padding: usize = 4 - packet.len() % 4;
         let block_total_length: u32 = packet.len() as u32 + packet_data_padding as u32 + 32;
-        let timestamp = self.start_time.elapsed().as_micros();
+        let timestamp = (self.clock.now() - self.start_time).as_micros();
 
         // Wrap the packet inside an Enhanced Packet Block.
         self.file.write(&u32::to_le_bytes(0x00000006)).await?; // Block Type
@@ -14,13 +14,36 @@
 
 use crate::uci_packets::PicaPosition;
 use glam::{EulerRot, Quat, Vec3};
-use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde::de::Error as _;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::convert::From;
 use std::default::Default;
 use std::fmt::Display;
 
+/// Cartesian coordinates in cm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Point {
+    /// Cartesian coordinates of the point, in m.
+    pub fn to_meters(self) -> Vec3 {
+        let cm = LengthUnit::Centimeter;
+        Vec3::new(
+            cm.to_meters(self.x),
+            cm.to_meters(self.y),
+            cm.to_meters(self.z),
+        )
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Position {
+    /// Cartesian coordinates, in m.
     position: Vec3,
     rotation: Quat,
 }
@@ -30,7 +53,7 @@ impl Display for Position {
         let (roll, pitch, yaw) = self.rotation.to_euler(EulerRot::ZXY);
         write!(
             f,
-            "Position: {}, {}, {} Rotation: {}, {}, {}",
+            "Position: {}m, {}m, {}m Rotation: {}, {}, {}",
             self.position.x,
             self.position.y,
             self.position.z,
@@ -41,25 +64,126 @@ impl Display for Position {
     }
 }
 
+/// Unit of the cartesian coordinates of a serialized position.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LengthUnit {
+    #[serde(rename = "m")]
+    Meter,
+    #[default]
+    #[serde(rename = "cm")]
+    Centimeter,
+    #[serde(rename = "mm")]
+    Millimeter,
+}
+
+impl LengthUnit {
+    pub(crate) fn to_meters(self, value: f32) -> f32 {
+        match self {
+            LengthUnit::Meter => value,
+            LengthUnit::Centimeter => value / 100.,
+            LengthUnit::Millimeter => value / 1000.,
+        }
+    }
+}
+
+/// Convert an angle to degrees rounded to the hundredth of degree, to hide
+/// the imprecision of the conversion from quaternion to euler angles.
+fn round_angle(angle: f32) -> f32 {
+    // Adding 0 turns a negative zero into a positive zero.
+    (angle.to_degrees() * 100.).round() / 100. + 0.
+}
+
+/// Floating-point form of a serialized position: cartesian
+/// coordinates in m, and yaw, pitch, roll angles in degrees.
+#[derive(Serialize, Deserialize)]
+struct PrecisePosition {
+    x: f32,
+    y: f32,
+    z: f32,
+    yaw: f32,
+    pitch: f32,
+    roll: f32,
+}
+
+/// Positions are serialized in the integer format of the previous
+/// versions, with coordinates in cm and angles in degrees, followed
+/// by the floating-point form in m under the `precise` key.
 impl Serialize for Position {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("Position", 5)?;
-        state.serialize_field("x", &(self.position.x as i16))?;
-        state.serialize_field("y", &(self.position.y as i16))?;
-        state.serialize_field("z", &(self.position.z as i16))?;
-
+        let cm = self.position * 100.;
         let (roll, pitch, yaw) = self.rotation.to_euler(EulerRot::ZXY);
 
+        let mut state = serializer.serialize_struct("Position", 7)?;
+        state.serialize_field("x", &(cm.x.round() as i32))?;
+        state.serialize_field("y", &(cm.y.round() as i32))?;
+        state.serialize_field("z", &(cm.z.round() as i32))?;
         state.serialize_field("yaw", &(yaw.to_degrees().round() as i16))?;
         state.serialize_field("pitch", &(pitch.to_degrees().round() as i8))?;
         state.serialize_field("roll", &(roll.to_degrees().round() as i16))?;
+        state.serialize_field(
+            "precise",
+            &PrecisePosition {
+                x: self.position.x,
+                y: self.position.y,
+                z: self.position.z,
+                yaw: round_angle(yaw),
+                pitch: round_angle(pitch),
+                roll: round_angle(roll),
+            },
+        )?;
         state.end()
     }
 }
 
+/// Serialized position. The floating-point form is used when present,
+/// otherwise the coordinates are read in the selected unit, in cm by
+/// default. Integer values from the previous format are accepted as is.
+#[derive(Deserialize)]
+struct SerializedPosition {
+    x: Option<f32>,
+    y: Option<f32>,
+    z: Option<f32>,
+    yaw: Option<f32>,
+    pitch: Option<f32>,
+    roll: Option<f32>,
+    #[serde(default)]
+    unit: LengthUnit,
+    precise: Option<PrecisePosition>,
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let position = SerializedPosition::deserialize(deserializer)?;
+        if let Some(precise) = position.precise {
+            return Ok(Position::from_meters(
+                precise.x,
+                precise.y,
+                precise.z,
+                precise.yaw,
+                precise.pitch,
+                precise.roll,
+            ));
+        }
+
+        let field = |value: Option<f32>, name| value.ok_or_else(|| D::Error::missing_field(name));
+        let unit = position.unit;
+        Ok(Position::from_meters(
+            unit.to_meters(field(position.x, "x")?),
+            unit.to_meters(field(position.y, "y")?),
+            unit.to_meters(field(position.z, "z")?),
+            field(position.yaw, "yaw")?,
+            field(position.pitch, "pitch")?,
+            field(position.roll, "roll")?,
+        ))
+    }
+}
+
 fn checked_div(num: f32, den: f32) -> Option<f32> {
     if den == 0. {
         None
@@ -89,22 +213,55 @@ fn elevation(delta: Vec3) -> f32 {
 }
 
 impl Position {
+    /// Create a position from cartesian coordinates in cm,
+    /// and yaw, pitch, roll angles in degrees.
     pub fn new(x: i16, y: i16, z: i16, yaw: i16, pitch: i8, roll: i16) -> Self {
+        let cm = LengthUnit::Centimeter;
+        Self::from_meters(
+            cm.to_meters(x as f32),
+            cm.to_meters(y as f32),
+            cm.to_meters(z as f32),
+            yaw as f32,
+            pitch as f32,
+            roll as f32,
+        )
+    }
+
+    /// Create a position from cartesian coordinates in m,
+    /// and yaw, pitch, roll angles in degrees.
+    pub fn from_meters(x: f32, y: f32, z: f32, yaw: f32, pitch: f32, roll: f32) -> Self {
         Self {
-            position: Vec3::new(x as f32, y as f32, z as f32),
+            position: Vec3::new(x, y, z),
             rotation: Quat::from_euler(
                 EulerRot::ZXY, // Rotation performed from right to left order
-                (roll as f32).to_radians(),
-                (pitch as f32).to_radians(),
-                (yaw as f32).to_radians(),
+                roll.to_radians(),
+                pitch.to_radians(),
+                yaw.to_radians(),
             ),
         }
     }
 
+    pub(crate) fn from_point_rotation(position: Vec3, rotation: Quat) -> Self {
+        Self { position, rotation }
+    }
+
+    /// Cartesian coordinates of the position, in m.
+    pub fn point(&self) -> Vec3 {
+        self.position
+    }
+
+    pub(crate) fn rotation(&self) -> Quat {
+        self.rotation
+    }
+
+    /// Return the range in cm, rounded and saturated to the maximum
+    /// range of 655.35 m representable in UCI range data notifications,
+    /// and the azimuth and elevation angles in degrees of the other
+    /// position relative to this one.
     pub fn compute_range_azimuth_elevation(&self, other: &Position) -> (u16, i16, i8) {
         let delta = other.position - self.position;
 
-        let distance = delta.length();
+        let distance = (delta.length() * 100.).round();
         let direction = self.rotation.mul_vec3(delta);
 
         let azimuth = azimuth(direction).to_degrees().round();
@@ -144,6 +301,54 @@ impl From<&PicaPosition> for Position {
 mod tests {
     use super::Position;
 
+    #[test]
+    fn serialization() {
+        let position: Position =
+            serde_json::from_str(r#"{"x":1,"y":-2,"z":3,"yaw":45,"pitch":-10,"roll":0}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&position).unwrap(),
+            r#"{"x":1,"y":-2,"z":3,"yaw":45,"pitch":-10,"roll":0,"#.to_owned()
+                + r#""precise":{"x":0.01,"y":-0.02,"z":0.03,"yaw":45.0,"pitch":-10.0,"roll":0.0}}"#
+        );
+
+        let position: Position = serde_json::from_str(
+            r#"{"x":1.5,"y":0.0042,"z":-400,"yaw":0,"pitch":0,"roll":0,"unit":"m"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_string(&position).unwrap(),
+            r#"{"x":150,"y":0,"z":-40000,"yaw":0,"pitch":0,"roll":0,"#.to_owned()
+                + r#""precise":{"x":1.5,"y":0.0042,"z":-400.0,"yaw":0.0,"pitch":0.0,"roll":0.0}}"#
+        );
+
+        let position: Position = serde_json::from_str(
+            r#"{"x":15,"y":2.5,"z":0,"yaw":0,"pitch":0,"roll":0,"unit":"mm"}"#,
+        )
+        .unwrap();
+        assert_eq!(position.point().x, 0.015);
+        assert_eq!(position.point().y, 0.0025);
+
+        // The floating-point form takes precedence over the integer fields.
+        let json = r#"{"x":0,"y":0,"z":0,"yaw":0,"pitch":0,"roll":0,"#.to_owned()
+            + r#""precise":{"x":1234.5,"y":0,"z":0,"yaw":0,"pitch":0,"roll":0}}"#;
+        let position: Position = serde_json::from_str(&json).unwrap();
+        assert_eq!(position.point().x, 1234.5);
+
+        assert!(serde_json::from_str::<Position>(r#"{"x":1,"y":-2,"z":3}"#).is_err());
+    }
+
+    #[test]
+    fn range_rounding() {
+        let position_a = Position::new(0, 0, 0, 0, 0, 0);
+        let position_b = Position::from_meters(0.996, 0., 0., 0., 0., 0.);
+        let (range, _, _) = position_a.compute_range_azimuth_elevation(&position_b);
+        assert_eq!(range, 100);
+
+        let position_b = Position::from_meters(1000., 0., 0., 0., 0., 0.);
+        let (range, _, _) = position_a.compute_range_azimuth_elevation(&position_b);
+        assert_eq!(range, u16::MAX);
+    }
+
     #[test]
     fn range() {
         let position_a = Position::new(0, 0, 0, 0, 0, 0);
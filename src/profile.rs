@@ -0,0 +1,264 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Identity and characteristics of the UWBS simulated for a UCI device.
+
+use crate::device::DEFAULT_CAPS_INFO;
+use crate::uci_packets::{AppConfigTlvType, CapTlvType, SessionType, StatusCode};
+use crate::{MacAddress, Position};
+use num_traits::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const UCI_VERSION: u16 = 0x1001; // Version 1.1.0
+pub const MAC_VERSION: u16 = 0x3001; // Version 1.3.0
+pub const PHY_VERSION: u16 = 0x3001; // Version 1.3.0
+pub const TEST_VERSION: u16 = 0x1001; // Version 1.1
+
+/// Generation of the UCI protocol emulated by a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UciProtocol {
+    /// FiRa UCI 1.x
+    V1,
+    /// FiRa UCI 2.0: session handles, data messages and
+    /// data credit notifications.
+    V2,
+}
+
+/// Profile of a UCI device. Versions use the encoding of the
+/// GET_DEVICE_INFO response. cf. [UCI] 6.4.1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceProfile {
+    /// MAC address of the device. The address is derived from
+    /// the device handle when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac_address: Option<MacAddress>,
+    /// Initial position of the device. Positions assigned by the
+    /// scene take precedence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    pub uci_version: u16,
+    pub mac_version: u16,
+    pub phy_version: u16,
+    pub test_version: u16,
+    /// Vendor specific information returned by GET_DEVICE_INFO.
+    pub vendor_spec_info: Vec<u8>,
+    /// Capabilities returned by GET_CAPS_INFO, indexed by TLV type.
+    pub capabilities: BTreeMap<u8, Vec<u8>>,
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        DeviceProfile {
+            mac_address: None,
+            position: None,
+            uci_version: UCI_VERSION,
+            mac_version: MAC_VERSION,
+            phy_version: PHY_VERSION,
+            test_version: TEST_VERSION,
+            vendor_spec_info: Vec::new(),
+            capabilities: DEFAULT_CAPS_INFO
+                .iter()
+                .map(|(id, value)| (id.to_u8().unwrap(), value.to_vec()))
+                .collect(),
+        }
+    }
+}
+
+impl DeviceProfile {
+    /// Parse a profile from JSON or YAML content.
+    pub fn parse(content: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(content)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !matches!(self.uci_version & 0xff, 1 | 2) {
+            return Err(format!("unsupported UCI version {:#06x}", self.uci_version));
+        }
+        if let Some(id) = self
+            .capabilities
+            .keys()
+            .find(|id| CapTlvType::from_u8(**id).is_none())
+        {
+            return Err(format!("unknown capability {:#04x}", id));
+        }
+        if self.vendor_spec_info.len() > u8::MAX as usize {
+            return Err("vendor specific information is too long".to_string());
+        }
+        Ok(())
+    }
+
+    /// Select the protocol from the major UCI version, encoded in
+    /// the first octet of the version. cf. [UCI] 6.4.1
+    pub fn protocol(&self) -> UciProtocol {
+        match self.uci_version & 0xff {
+            2 => UciProtocol::V2,
+            _ => UciProtocol::V1,
+        }
+    }
+
+    /// Return true if the bit is set in the capability bitmap.
+    /// Missing capabilities are not supported.
+    fn has_capability_bit(&self, id: CapTlvType, bit: u8) -> bool {
+        self.capabilities
+            .get(&id.to_u8().unwrap())
+            .and_then(|value| value.get(bit as usize / 8))
+            .map_or(false, |octet| octet & (1 << (bit % 8)) != 0)
+    }
+
+    /// Return true if sessions of this type can be initialized.
+    /// FiRa sessions require the FiRa version capabilities, and
+    /// CCC sessions the CCC version capability.
+    pub fn supports_session_type(&self, session_type: SessionType) -> bool {
+        let has_capability = |id: CapTlvType| self.capabilities.contains_key(&id.to_u8().unwrap());
+        match session_type {
+            SessionType::FiraRangingSession | SessionType::FiraDataTransfer => {
+                has_capability(CapTlvType::SupportedFiraPhyVersionRange)
+                    && has_capability(CapTlvType::SupportedFiraMacVersionRange)
+            }
+            SessionType::Ccc => has_capability(CapTlvType::CccSupportedVersions),
+        }
+    }
+
+    /// Check an application configuration parameter against the
+    /// capabilities. cf. [UCI] 8.5.3.2 Table 45
+    pub fn check_app_config(&self, id: AppConfigTlvType, value: &[u8]) -> Result<(), StatusCode> {
+        let supported = match (id, value) {
+            (_, []) => true,
+            (AppConfigTlvType::ChannelNumber, [channel]) => {
+                match [5, 6, 8, 9, 10, 12, 13, 14]
+                    .iter()
+                    .position(|supported| supported == channel)
+                {
+                    Some(bit) => self.has_capability_bit(CapTlvType::SupportedChannels, bit as u8),
+                    None => true,
+                }
+            }
+            (AppConfigTlvType::StsConfig, [sts_config]) => {
+                self.has_capability_bit(CapTlvType::SupportedStsConfig, *sts_config)
+            }
+            (AppConfigTlvType::MacAddressMode, [mode]) => {
+                *mode == 0 || self.has_capability_bit(CapTlvType::SupportedExtendedMacAddress, 0)
+            }
+            (AppConfigTlvType::AoaResultReq, [aoa_result_req]) => {
+                *aoa_result_req == 0
+                    || self
+                        .capabilities
+                        .get(&CapTlvType::SupportedAoa.to_u8().unwrap())
+                        .map_or(false, |value| value.iter().any(|octet| *octet != 0))
+            }
+            _ => true,
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(StatusCode::UciStatusInvalidParam)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let profile = DeviceProfile::parse(
+            r#"
+mac_address: "00:10"
+uci_version: 0x1101
+vendor_spec_info: [1, 2]
+capabilities:
+  0x02: [0x1]
+"#,
+        )
+        .unwrap();
+        assert!(profile.validate().is_ok());
+        assert_eq!(profile.mac_address, Some(MacAddress::Short([0, 0x10])));
+        assert_eq!(profile.uci_version, 0x1101);
+        assert_eq!(profile.protocol(), UciProtocol::V1);
+        assert_eq!(profile.mac_version, MAC_VERSION);
+        assert_eq!(profile.capabilities, BTreeMap::from([(2, vec![1])]));
+
+        assert!(DeviceProfile::default().validate().is_ok());
+        let profile = DeviceProfile {
+            uci_version: 0x0002,
+            ..Default::default()
+        };
+        assert!(profile.validate().is_ok());
+        assert_eq!(profile.protocol(), UciProtocol::V2);
+        let profile = DeviceProfile {
+            uci_version: 0x0003,
+            ..Default::default()
+        };
+        assert!(profile.validate().is_err());
+        let profile = DeviceProfile {
+            capabilities: BTreeMap::from([(0x12, vec![])]),
+            ..Default::default()
+        };
+        assert!(profile.validate().is_err());
+    }
+
+    #[test]
+    fn check_app_config() {
+        let profile = DeviceProfile::default();
+        assert!(profile.supports_session_type(SessionType::FiraRangingSession));
+        assert!(profile
+            .check_app_config(AppConfigTlvType::ChannelNumber, &[9])
+            .is_ok());
+        assert!(profile
+            .check_app_config(AppConfigTlvType::AoaResultReq, &[1])
+            .is_ok());
+        assert!(profile
+            .check_app_config(AppConfigTlvType::MacAddressMode, &[2])
+            .is_ok());
+
+        let profile = DeviceProfile {
+            capabilities: BTreeMap::from([
+                (CapTlvType::SupportedChannels.to_u8().unwrap(), vec![0x08]),
+                (CapTlvType::SupportedAoa.to_u8().unwrap(), vec![0x00]),
+                (
+                    CapTlvType::CccSupportedVersions.to_u8().unwrap(),
+                    vec![1, 0],
+                ),
+            ]),
+            ..Default::default()
+        };
+        assert!(!profile.supports_session_type(SessionType::FiraRangingSession));
+        assert!(profile.supports_session_type(SessionType::Ccc));
+        assert!(profile
+            .check_app_config(AppConfigTlvType::ChannelNumber, &[9])
+            .is_ok());
+        assert_eq!(
+            profile.check_app_config(AppConfigTlvType::ChannelNumber, &[5]),
+            Err(StatusCode::UciStatusInvalidParam)
+        );
+        assert!(profile
+            .check_app_config(AppConfigTlvType::AoaResultReq, &[0])
+            .is_ok());
+        assert!(profile
+            .check_app_config(AppConfigTlvType::AoaResultReq, &[1])
+            .is_err());
+        assert!(profile
+            .check_app_config(AppConfigTlvType::MacAddressMode, &[0])
+            .is_ok());
+        assert!(profile
+            .check_app_config(AppConfigTlvType::MacAddressMode, &[2])
+            .is_err());
+        assert!(profile
+            .check_app_config(AppConfigTlvType::StsConfig, &[0])
+            .is_err());
+    }
+}
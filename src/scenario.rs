@@ -0,0 +1,439 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timeline of commands executed against Pica, with assertions
+//! on the emitted events.
+
+use crate::{
+    AnchorConfig, AntennaPattern, Clock, MacAddress, NoiseModel, Obstacle, PicaCommand,
+    PicaCommandError, PicaEvent, Position, Scene, Trajectory, Zone,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+/// Default time given to an expected event to be emitted, in seconds.
+const DEFAULT_EXPECT_TIMEOUT: f32 = 1.;
+
+fn default_expect_timeout() -> f32 {
+    DEFAULT_EXPECT_TIMEOUT
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ScenarioError {
+    #[error("Step {0} is not scheduled after the previous step")]
+    InvalidTimeline(usize),
+    #[error("Step {step} failed: {error}")]
+    Command {
+        step: usize,
+        error: PicaCommandError,
+    },
+    #[error("Step {step} failed: no {event} event matching {pattern} within {timeout}s")]
+    Expectation {
+        step: usize,
+        event: String,
+        pattern: Value,
+        timeout: f32,
+    },
+    #[error("Step {step} failed: {count} events were missed while waiting for the {event} event")]
+    Lagged {
+        step: usize,
+        event: String,
+        count: u64,
+    },
+    #[error("Pica is not running")]
+    Closed,
+}
+
+/// Event expected to be emitted by Pica.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Expectation {
+    /// Name of the event, e.g. neighbor-updated.
+    pub event: String,
+    /// Fields of the event. The expectation is met by the first event
+    /// whose fields include all the fields of the pattern.
+    #[serde(default, rename = "match")]
+    pub pattern: Value,
+    /// Time given to the event to be emitted, in simulated seconds.
+    #[serde(default = "default_expect_timeout")]
+    pub timeout: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    CreateAnchor {
+        mac_address: MacAddress,
+        #[serde(default)]
+        position: Position,
+    },
+    DestroyAnchor {
+        mac_address: MacAddress,
+    },
+    SetPosition {
+        mac_address: MacAddress,
+        position: Position,
+    },
+    SetTrajectory {
+        mac_address: MacAddress,
+        trajectory: Trajectory,
+    },
+    ClearTrajectory {
+        mac_address: MacAddress,
+    },
+    SetNoiseModel {
+        mac_address: MacAddress,
+        #[serde(default)]
+        peer_mac_address: Option<MacAddress>,
+        model: NoiseModel,
+    },
+    SetAntennaPattern {
+        mac_address: MacAddress,
+        pattern: AntennaPattern,
+    },
+    SetAnchorConfig {
+        mac_address: MacAddress,
+        config: AnchorConfig,
+    },
+    CreateObstacle {
+        name: String,
+        obstacle: Obstacle,
+    },
+    DestroyObstacle {
+        name: String,
+    },
+    CreateZone {
+        name: String,
+        zone: Zone,
+    },
+    DestroyZone {
+        name: String,
+    },
+    LoadScene(Scene),
+    Expect(Expectation),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    /// Time of execution of the step, in simulated seconds
+    /// from the start of the scenario.
+    pub at: f32,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Steps executed in order at the selected times. Expectations are
+/// met by events emitted after the last action preceding them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scenario {
+    pub steps: Vec<Step>,
+}
+
+/// Return true if the value includes all the fields of the pattern.
+fn matches(pattern: &Value, value: &Value) -> bool {
+    match (pattern, value) {
+        (Value::Null, _) => true,
+        (Value::Object(pattern), Value::Object(value)) => pattern.iter().all(|(key, pattern)| {
+            value
+                .get(key)
+                .map_or(false, |value| matches(pattern, value))
+        }),
+        (Value::Number(pattern), Value::Number(value)) => pattern.as_f64() == value.as_f64(),
+        _ => pattern == value,
+    }
+}
+
+impl Expectation {
+    async fn wait(
+        &self,
+        step: usize,
+        clock: &Clock,
+        events: &mut broadcast::Receiver<PicaEvent>,
+    ) -> Result<(), ScenarioError> {
+        let deadline = clock.now() + Duration::from_secs_f32(self.timeout.max(0.));
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => Ok(event),
+                _ = clock.sleep_until(deadline) => Err(()),
+            };
+            match event {
+                Ok(Ok(event)) => {
+                    if event.name() == self.event
+                        && matches(&self.pattern, &serde_json::to_value(&event).unwrap())
+                    {
+                        return Ok(());
+                    }
+                }
+                Ok(Err(RecvError::Lagged(count))) => {
+                    return Err(ScenarioError::Lagged {
+                        step,
+                        event: self.event.clone(),
+                        count,
+                    })
+                }
+                Ok(Err(RecvError::Closed)) => return Err(ScenarioError::Closed),
+                Err(_) => {
+                    return Err(ScenarioError::Expectation {
+                        step,
+                        event: self.event.clone(),
+                        pattern: self.pattern.clone(),
+                        timeout: self.timeout,
+                    })
+                }
+            }
+        }
+    }
+}
+
+impl Action {
+    async fn execute(
+        self,
+        step: usize,
+        tx: &mpsc::Sender<PicaCommand>,
+    ) -> Result<(), ScenarioError> {
+        let (rsp_tx, rsp_rx) = oneshot::channel();
+        let command = match self {
+            Action::CreateAnchor {
+                mac_address,
+                position,
+            } => PicaCommand::CreateAnchor(mac_address, position, rsp_tx),
+            Action::DestroyAnchor { mac_address } => {
+                PicaCommand::DestroyAnchor(mac_address, rsp_tx)
+            }
+            Action::SetPosition {
+                mac_address,
+                position,
+            } => PicaCommand::SetPosition(mac_address, position, rsp_tx),
+            Action::SetTrajectory {
+                mac_address,
+                trajectory,
+            } => PicaCommand::SetTrajectory(mac_address, trajectory, rsp_tx),
+            Action::ClearTrajectory { mac_address } => {
+                PicaCommand::ClearTrajectory(mac_address, rsp_tx)
+            }
+            Action::SetNoiseModel {
+                mac_address,
+                peer_mac_address,
+                model,
+            } => PicaCommand::SetNoiseModel(mac_address, peer_mac_address, model, rsp_tx),
+            Action::SetAntennaPattern {
+                mac_address,
+                pattern,
+            } => PicaCommand::SetAntennaPattern(mac_address, pattern, rsp_tx),
+            Action::SetAnchorConfig {
+                mac_address,
+                config,
+            } => PicaCommand::SetAnchorConfig(mac_address, config, rsp_tx),
+            Action::CreateObstacle { name, obstacle } => {
+                PicaCommand::CreateObstacle(name, obstacle, rsp_tx)
+            }
+            Action::DestroyObstacle { name } => PicaCommand::DestroyObstacle(name, rsp_tx),
+            Action::CreateZone { name, zone } => PicaCommand::CreateZone(name, zone, rsp_tx),
+            Action::DestroyZone { name } => PicaCommand::DestroyZone(name, rsp_tx),
+            Action::LoadScene(scene) => PicaCommand::LoadScene(scene, rsp_tx),
+            Action::Expect(_) => unreachable!(),
+        };
+
+        tx.send(command).await.map_err(|_| ScenarioError::Closed)?;
+        rsp_rx
+            .await
+            .map_err(|_| ScenarioError::Closed)?
+            .map_err(|error| ScenarioError::Command { step, error })
+    }
+}
+
+impl Scenario {
+    /// Parse a scenario from JSON or YAML content.
+    pub fn parse(content: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(content)
+    }
+
+    /// Execute the scenario. The events must be subscribed to
+    /// before the scenario is started.
+    pub async fn run(
+        self,
+        clock: Clock,
+        tx: mpsc::Sender<PicaCommand>,
+        mut events: broadcast::Receiver<PicaEvent>,
+    ) -> Result<(), ScenarioError> {
+        let mut previous = 0.;
+        for (index, step) in self.steps.iter().enumerate() {
+            if !step.at.is_finite() || step.at < previous {
+                return Err(ScenarioError::InvalidTimeline(index));
+            }
+            previous = step.at;
+        }
+
+        let start = clock.now();
+        for (index, step) in self.steps.into_iter().enumerate() {
+            clock
+                .sleep_until(start + Duration::from_secs_f32(step.at))
+                .await;
+            println!(
+                "[scenario] Step {} at {}s: {:?}",
+                index, step.at, step.action
+            );
+
+            match step.action {
+                Action::Expect(expectation) => expectation.wait(index, &clock, &mut events).await?,
+                action => {
+                    // Discard the events emitted before the action.
+                    while let Ok(_) | Err(TryRecvError::Lagged(_)) = events.try_recv() {}
+                    action.execute(index, &tx).await?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Category, Pica};
+
+    const SCENARIO: &str = r#"
+steps:
+  - at: 0
+    create-anchor: { mac_address: "00:05" }
+  - at: 0
+    expect: { event: device-added, match: { mac_address: "00:05" } }
+  - at: 0.1
+    create-anchor:
+      mac_address: "00:06"
+      position: { x: 100, y: 0, z: 0, yaw: 0, pitch: 0, roll: 0 }
+  - at: 0.2
+    set-position:
+      mac_address: "00:06"
+      position: { x: 0, y: 200, z: 0, yaw: 0, pitch: 0, roll: 0 }
+  - at: 0.2
+    expect:
+      event: neighbor-updated
+      match: { source_mac_address: "00:06", distance: 200 }
+"#;
+
+    fn start_pica() -> (
+        Clock,
+        mpsc::Sender<PicaCommand>,
+        broadcast::Receiver<PicaEvent>,
+    ) {
+        let (event_tx, events) = broadcast::channel(16);
+        let mut pica = Pica::new(event_tx, None);
+        let (clock, tx) = (pica.clock(), pica.tx());
+        tokio::spawn(async move { pica.run().await });
+        (clock, tx, events)
+    }
+
+    #[tokio::test]
+    async fn run() {
+        let (clock, tx, events) = start_pica();
+        let scenario = Scenario::parse(SCENARIO).unwrap();
+        assert_eq!(scenario.clone().run(clock, tx, events).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn failures() {
+        let (clock, tx, events) = start_pica();
+        let mut scenario = Scenario::parse(SCENARIO).unwrap();
+        scenario.steps[1].action = Action::DestroyAnchor {
+            mac_address: MacAddress::Short([0, 7]),
+        };
+        assert_eq!(
+            scenario.run(clock, tx, events).await,
+            Err(ScenarioError::Command {
+                step: 1,
+                error: PicaCommandError::DeviceNotFound(MacAddress::Short([0, 7]))
+            })
+        );
+
+        let (clock, tx, events) = start_pica();
+        let mut scenario = Scenario::parse(SCENARIO).unwrap();
+        scenario.steps[4].action = Action::Expect(Expectation {
+            event: "neighbor-updated".to_string(),
+            pattern: serde_json::json!({ "distance": 100 }),
+            timeout: 0.1,
+        });
+        assert!(matches!(
+            scenario.run(clock, tx, events).await,
+            Err(ScenarioError::Expectation { step: 4, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn zones() {
+        let (clock, tx, events) = start_pica();
+        let scenario = Scenario::parse(
+            r#"
+steps:
+  - at: 0
+    create-anchor: { mac_address: "00:05" }
+  - at: 0
+    create-zone:
+      name: door
+      zone: { shape: sphere, center: { x: 100, y: 0, z: 0 }, radius: 50 }
+  - at: 0.1
+    set-position:
+      mac_address: "00:05"
+      position: { x: 80, y: 0, z: 0, yaw: 0, pitch: 0, roll: 0 }
+  - at: 0.1
+    expect: { event: zone-entered, match: { zone: door, mac_address: "00:05", x: 80 } }
+  - at: 0.2
+    destroy-anchor: { mac_address: "00:05" }
+  - at: 0.2
+    expect: { event: zone-exited, match: { zone: door, category: Anchor } }
+"#,
+        )
+        .unwrap();
+        assert_eq!(scenario.run(clock, tx, events).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn lagged() {
+        let (event_tx, mut events) = broadcast::channel(1);
+        for zone in ["door", "room"] {
+            event_tx
+                .send(PicaEvent::ZoneExited {
+                    zone: zone.to_string(),
+                    category: Category::Anchor,
+                    mac_address: MacAddress::Short([0, 5]),
+                })
+                .unwrap();
+        }
+        let expectation = Expectation {
+            event: "zone-exited".to_string(),
+            pattern: Value::Null,
+            timeout: 1.,
+        };
+        assert_eq!(
+            expectation.wait(3, &Clock::new(), &mut events).await,
+            Err(ScenarioError::Lagged {
+                step: 3,
+                event: "zone-exited".to_string(),
+                count: 1
+            })
+        );
+    }
+
+    #[test]
+    fn pattern() {
+        let value = serde_json::json!({ "mac_address": "00:01", "x": 1.0, "y": 2.0 });
+        assert!(matches(&Value::Null, &value));
+        assert!(matches(&serde_json::json!({ "x": 1 }), &value));
+        assert!(!matches(&serde_json::json!({ "x": 2 }), &value));
+        assert!(!matches(&serde_json::json!({ "z": 1 }), &value));
+    }
+}
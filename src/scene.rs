@@ -0,0 +1,120 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Description of a simulated scene, saved to and loaded from
+//! JSON or YAML files.
+
+use crate::anchor::AnchorConfig;
+use crate::mac_address::MacAddress;
+use crate::obstacle::Obstacle;
+use crate::position::Position;
+use crate::trajectory::Trajectory;
+use crate::zone::Zone;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Device placed in the scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneDevice {
+    pub mac_address: MacAddress,
+    #[serde(default)]
+    pub position: Position,
+    /// Trajectory followed by the device from the moment
+    /// the scene is loaded, or the device is connected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trajectory: Option<Trajectory>,
+    /// Configuration of the anchor, ignored for UCI devices.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<AnchorConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scene {
+    /// Anchors created when the scene is loaded.
+    #[serde(default)]
+    pub anchors: Vec<SceneDevice>,
+    /// Positions of the UCI devices. The positions are applied
+    /// to the UCI devices with matching MAC addresses when
+    /// they connect.
+    #[serde(default)]
+    pub devices: Vec<SceneDevice>,
+    /// Obstacles indexed by name.
+    #[serde(default)]
+    pub obstacles: BTreeMap<String, Obstacle>,
+    /// Zones indexed by name.
+    #[serde(default)]
+    pub zones: BTreeMap<String, Zone>,
+}
+
+impl Scene {
+    /// Parse a scene from JSON or YAML content.
+    /// JSON is parsed as YAML, of which it is a subset.
+    pub fn parse(content: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(content)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let yaml = r#"
+anchors:
+  - mac_address: "00:01"
+    position: { x: 1.5, y: 0, z: 0, yaw: 90, pitch: 0, roll: 0, unit: m }
+  - mac_address: "00:02"
+    trajectory:
+      loop: true
+      waypoints:
+        - position: { x: 0, y: 0, z: 0, yaw: 0, pitch: 0, roll: 0 }
+        - position: { x: 100, y: 0, z: 0, yaw: 0, pitch: 0, roll: 0 }
+          speed: 50
+devices:
+  - mac_address: "00:00"
+obstacles:
+  wall:
+    min: { x: -100, y: -100, z: 50 }
+    max: { x: 100, y: 100, z: 60 }
+    attenuation: 10
+"#;
+        let scene = Scene::parse(yaml).unwrap();
+        assert_eq!(scene.anchors.len(), 2);
+        assert_eq!(scene.anchors[0].position.point().x, 1.5);
+        assert!(scene.anchors[1].trajectory.as_ref().unwrap().looping);
+        assert_eq!(scene.devices[0].mac_address, MacAddress::Short([0, 0]));
+        assert_eq!(scene.obstacles["wall"].attenuation, 10.);
+
+        let json = serde_json::to_string(&scene).unwrap();
+        let reloaded = Scene::parse(&json).unwrap();
+        assert_eq!(reloaded.anchors[0].position.point().x, 1.5);
+        assert_eq!(reloaded.obstacles["wall"].range_bias, 30.);
+
+        let reloaded = Scene::parse(&scene.to_yaml().unwrap()).unwrap();
+        assert_eq!(
+            reloaded.anchors[1]
+                .trajectory
+                .as_ref()
+                .unwrap()
+                .waypoints
+                .len(),
+            2
+        );
+    }
+}
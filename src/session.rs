@@ -16,6 +16,12 @@
 //! - [MAC] FiRa Consortium UWB MAC Technical Requirements
 //! - [UCI] FiRa Consortium UWB Command Interface Generic Technical specification
 
+use crate::anchor::StsKey;
+use crate::clock::Clock;
+use crate::info::{AppConfigInfo, SessionInfo};
+use crate::link_budget::LinkBudget;
+use crate::profile::DeviceProfile;
+use crate::snapshot::SessionSnapshot;
 use crate::uci_packets::AppConfigTlvType;
 use crate::uci_packets::*;
 use crate::{MacAddress, PicaCommand};
@@ -26,7 +32,8 @@ use tokio::task::JoinHandle;
 use tokio::time;
 
 use num_derive::{FromPrimitive, ToPrimitive};
-use num_traits::FromPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
 
 pub const MAX_SESSION: usize = 255;
 pub const DEFAULT_RANGING_INTERVAL: Duration = time::Duration::from_millis(200);
@@ -34,20 +41,26 @@ pub const DEFAULT_SLOT_DURATION: u16 = 2400; // RTSU unit
 /// cf. [UCI] 8.3 Table 29
 pub const MAX_NUMBER_OF_CONTROLEES: usize = 8;
 
-#[derive(Copy, Clone, FromPrimitive, PartialEq, Eq)]
+/// cf. [UCI] 8.3 Table 29
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
 pub enum DeviceType {
-    /// [MAC] 5.1.1 Device controlling the ranging features through Control Messages
-    Controller,
     /// [MAC] 5.1.2 Device utilizing the ranging features set through Control Messages
-    Controlee,
+    Controlee = 0x00,
+    /// [MAC] 5.1.1 Device controlling the ranging features through Control Messages
+    Controller = 0x01,
 }
 
-#[derive(Copy, Clone, FromPrimitive)]
+/// cf. [UCI] 8.3 Table 29
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
 pub enum DeviceRole {
-    /// [MAC] 5.1.3 Device initiating a ranging exchange with a ranging initiation message
-    Initiator,
     /// [MAC] 5.1.4 Device responding to ranging initiation messages
-    Responder,
+    Responder = 0x00,
+    /// [MAC] 5.1.3 Device initiating a ranging exchange with a ranging initiation message
+    Initiator = 0x01,
 }
 
 /// cf. [UCI] 8.4 Table 29
@@ -63,7 +76,7 @@ pub enum MacAddressMode {
 }
 
 /// cf. [UCI] 8.3 Table 29
-#[derive(Copy, Clone, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ChannelNumber {
     ChannelNumber5 = 0x05,
@@ -76,6 +89,15 @@ pub enum ChannelNumber {
     ChannelNumber14 = 0x0e,
 }
 
+/// cf. [UCI] 8.3 Table 29
+#[derive(Copy, Clone, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[repr(u8)]
+enum StsConfig {
+    Static = 0x00,
+    Dynamic = 0x01,
+    DynamicForControleeIndividualKey = 0x02,
+}
+
 const DEFAULT_CHANNEL_NUMBER: ChannelNumber = ChannelNumber::ChannelNumber9;
 
 /// cf. [UCI] 8.3 Table 29
@@ -102,8 +124,10 @@ pub struct AppConfig {
     /// Copy of the valid App Configuration parameters provided by host
     raw: HashMap<AppConfigTlvType, Vec<u8>>,
 
-    device_type: DeviceType,
-    _device_role: DeviceRole,
+    /// Unset when not configured by the host.
+    device_type: Option<DeviceType>,
+    /// Unset when not configured by the host.
+    device_role: Option<DeviceRole>,
     mac_address_mode: MacAddressMode,
     device_mac_address: MacAddress,
     number_of_controlees: usize,
@@ -112,6 +136,10 @@ pub struct AppConfig {
     slot_duration: u16,
     channel_number: ChannelNumber,
     multi_node_mode: MultiNodeMode,
+    tx_adaptive_payload_power: bool,
+    sts_config: StsConfig,
+    vendor_id: Option<[u8; 2]>,
+    static_sts_iv: Option<[u8; 6]>,
 }
 
 impl Default for AppConfig {
@@ -119,8 +147,8 @@ impl Default for AppConfig {
         AppConfig {
             raw: HashMap::new(),
             mac_address_mode: MacAddressMode::AddressMode0,
-            _device_role: DeviceRole::Responder,
-            device_type: DeviceType::Controlee,
+            device_role: None,
+            device_type: None,
             ranging_interval: DEFAULT_RANGING_INTERVAL,
             slot_duration: DEFAULT_SLOT_DURATION,
             channel_number: DEFAULT_CHANNEL_NUMBER,
@@ -128,10 +156,35 @@ impl Default for AppConfig {
             number_of_controlees: 0,
             dst_mac_addresses: Vec::new(),
             multi_node_mode: MultiNodeMode::Unicast,
+            tx_adaptive_payload_power: false,
+            sts_config: StsConfig::Static,
+            vendor_id: None,
+            static_sts_iv: None,
         }
     }
 }
 
+/// Value of a single octet app config parameter.
+fn parse_octet(value: &[u8]) -> Result<u8, StatusCode> {
+    match value {
+        [octet] => Ok(*octet),
+        _ => Err(StatusCode::UciStatusInvalidParam),
+    }
+}
+
+/// Value of a single octet app config parameter, checked
+/// against the values of the enumeration.
+fn parse_enum<T: FromPrimitive>(value: &[u8]) -> Result<T, StatusCode> {
+    T::from_u8(parse_octet(value)?).ok_or(StatusCode::UciStatusInvalidParam)
+}
+
+/// Value of a fixed length app config parameter.
+fn parse_array<const N: usize>(value: &[u8]) -> Result<[u8; N], StatusCode> {
+    value
+        .try_into()
+        .map_err(|_| StatusCode::UciStatusInvalidParam)
+}
+
 fn app_config_has_mandatory_parameters(configs: &[AppConfigParameter]) -> bool {
     const MANDATORY_PARAMETERS: [AppConfigTlvType; 6] = [
         AppConfigTlvType::DeviceRole,
@@ -157,60 +210,66 @@ impl AppConfig {
     ) -> std::result::Result<(), StatusCode> {
         match id {
             AppConfigTlvType::MacAddressMode => {
-                let mode = MacAddressMode::from_u8(value[0]).unwrap();
+                let mode: MacAddressMode = parse_enum(value)?;
                 if mode == MacAddressMode::AddressMode1 {
                     return Err(StatusCode::UciStatusInvalidParam);
                 }
                 self.mac_address_mode = mode;
             }
             AppConfigTlvType::RangingInterval => {
-                let interval = u32::from_le_bytes(value[..].try_into().unwrap());
+                let interval = u32::from_le_bytes(parse_array(value)?);
                 self.ranging_interval = time::Duration::from_millis(interval as u64)
             }
             AppConfigTlvType::SlotDuration => {
-                self.slot_duration = u16::from_le_bytes(value[..].try_into().unwrap())
-            }
-            AppConfigTlvType::ChannelNumber => {
-                self.channel_number = ChannelNumber::from_u8(value[0]).unwrap()
+                self.slot_duration = u16::from_le_bytes(parse_array(value)?)
             }
+            AppConfigTlvType::ChannelNumber => self.channel_number = parse_enum(value)?,
             AppConfigTlvType::DeviceMacAddress => {
                 self.device_mac_address = match self.mac_address_mode {
-                    MacAddressMode::AddressMode0 => {
-                        MacAddress::Short(value[..].try_into().unwrap())
-                    }
-                    MacAddressMode::AddressMode2 => {
-                        MacAddress::Extend(value[..].try_into().unwrap())
-                    }
-                    _ => panic!("Unexpected MAC Address Mode"),
+                    MacAddressMode::AddressMode0 => MacAddress::Short(parse_array(value)?),
+                    MacAddressMode::AddressMode2 => MacAddress::Extend(parse_array(value)?),
+                    _ => return Err(StatusCode::UciStatusInvalidParam),
                 };
             }
             AppConfigTlvType::NoOfControlee => {
-                assert!(value[0] as usize <= MAX_NUMBER_OF_CONTROLEES);
-                self.number_of_controlees = value[0] as usize;
+                let number_of_controlees = parse_octet(value)? as usize;
+                if number_of_controlees > MAX_NUMBER_OF_CONTROLEES {
+                    return Err(StatusCode::UciStatusInvalidParam);
+                }
+                self.number_of_controlees = number_of_controlees;
             }
             AppConfigTlvType::DstMacAddress => {
                 let mac_address_size = match self.mac_address_mode {
                     MacAddressMode::AddressMode0 => 2,
                     MacAddressMode::AddressMode2 => 8,
-                    _ => panic!("Unexpected MAC Address Mode"),
+                    _ => return Err(StatusCode::UciStatusInvalidParam),
                 };
-                if value.len() % mac_address_size != 0
-                    || (value.len() / mac_address_size) != self.number_of_controlees
-                {
+                if value.len() != mac_address_size * self.number_of_controlees {
                     return Err(StatusCode::UciStatusInvalidParam);
                 }
                 self.dst_mac_addresses = value
                     .chunks(mac_address_size)
                     .map(|c| match self.mac_address_mode {
                         MacAddressMode::AddressMode0 => MacAddress::Short(c.try_into().unwrap()),
-                        MacAddressMode::AddressMode2 => MacAddress::Extend(c.try_into().unwrap()),
-                        _ => panic!("Unexpected MAC Address Mode"),
+                        _ => MacAddress::Extend(c.try_into().unwrap()),
                     })
                     .collect();
-                assert_eq!(self.dst_mac_addresses.len(), self.number_of_controlees);
             }
-            AppConfigTlvType::MultiNodeMode => {
-                self.multi_node_mode = MultiNodeMode::from_u8(value[0]).unwrap()
+            AppConfigTlvType::MultiNodeMode => self.multi_node_mode = parse_enum(value)?,
+            AppConfigTlvType::DeviceType => self.device_type = Some(parse_enum(value)?),
+            AppConfigTlvType::DeviceRole => self.device_role = Some(parse_enum(value)?),
+            AppConfigTlvType::StsConfig => self.sts_config = parse_enum(value)?,
+            AppConfigTlvType::VendorId => self.vendor_id = Some(parse_array(value)?),
+            AppConfigTlvType::StaticStsIv => self.static_sts_iv = Some(parse_array(value)?),
+            AppConfigTlvType::AoaResultReq => {
+                parse_octet(value)?;
+            }
+            AppConfigTlvType::TxAdaptivePayloadPower => {
+                self.tx_adaptive_payload_power = match parse_octet(value)? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(StatusCode::UciStatusInvalidParam),
+                }
             }
             id => {
                 println!("Ignored AppConfig parameter {}", id);
@@ -227,7 +286,12 @@ impl AppConfig {
         self.raw.get(&id).cloned()
     }
 
-    fn extend(&mut self, configs: &[AppConfigParameter]) -> Vec<AppConfigStatus> {
+    /// Apply the parameters supported by the device profile.
+    fn extend(
+        &mut self,
+        configs: &[AppConfigParameter],
+        profile: &DeviceProfile,
+    ) -> Vec<AppConfigStatus> {
         if !app_config_has_mandatory_parameters(configs) {
             // TODO: What shall we do in this situation?
         }
@@ -236,7 +300,10 @@ impl AppConfig {
             .iter()
             .fold(Vec::new(), |mut invalid_parameters, config| {
                 match AppConfigTlvType::from_u8(config.id) {
-                    Some(id) => match self.set_config(id, &config.value) {
+                    Some(id) => match profile
+                        .check_app_config(id, &config.value)
+                        .and_then(|_| self.set_config(id, &config.value))
+                    {
                         Ok(_) => (),
                         Err(status) => invalid_parameters.push(AppConfigStatus {
                             config_id: config.id,
@@ -262,10 +329,14 @@ pub struct Session {
 
     session_type: SessionType,
     pub sequence_number: u32,
+    /// Number of range data notifications sent, not restored
+    /// from snapshots.
+    pub ranging_count: u32,
     app_config: AppConfig,
     ranging_task: Option<JoinHandle<()>>,
     tx: mpsc::Sender<UciPacketPacket>,
     pica_tx: mpsc::Sender<PicaCommand>,
+    clock: Clock,
 }
 
 impl Session {
@@ -275,6 +346,7 @@ impl Session {
         device_handle: usize,
         tx: mpsc::Sender<UciPacketPacket>,
         pica_tx: mpsc::Sender<PicaCommand>,
+        clock: Clock,
     ) -> Self {
         Self {
             state: SessionState::SessionStateDeinit,
@@ -282,10 +354,12 @@ impl Session {
             device_handle,
             session_type,
             sequence_number: 0,
+            ranging_count: 0,
             app_config: AppConfig::default(),
             ranging_task: None,
             tx,
             pica_tx,
+            clock,
         }
     }
 
@@ -295,29 +369,106 @@ impl Session {
             return;
         }
 
-        // Send status notification
+        // Send status notification, the host may already be disconnected.
         self.state = session_state;
         let tx = self.tx.clone();
         let session_id = self.id;
         tokio::spawn(async move {
-            tx.send(
-                SessionStatusNtfBuilder {
-                    session_id,
-                    session_state,
-                    reason_code: ReasonCode::StateChangeWithSessionManagementCommands,
-                }
-                .build()
-                .into(),
-            )
-            .await
-            .unwrap()
+            let _ = tx
+                .send(
+                    SessionStatusNtfBuilder {
+                        session_id,
+                        session_state,
+                        reason_code: ReasonCode::StateChangeWithSessionManagementCommands,
+                    }
+                    .build()
+                    .into(),
+                )
+                .await;
         });
     }
 
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Return true if the session is an active controlee of the
+    /// controller session run by the device with the given address.
+    /// Controlee sessions list the address of their controller
+    /// as destination address.
+    pub fn is_controlee_of(
+        &self,
+        controller: &Session,
+        controller_mac_address: MacAddress,
+    ) -> bool {
+        self.state == SessionState::SessionStateActive
+            && self.id == controller.id
+            && self.get_device_type() == Some(DeviceType::Controlee)
+            && self.get_device_role() != controller.get_device_role()
+            && self.get_channel_number() == controller.get_channel_number()
+            && self
+                .get_dst_mac_addresses()
+                .contains(&controller_mac_address)
+    }
+
+    pub fn get_device_type(&self) -> Option<DeviceType> {
+        self.app_config.device_type
+    }
+
+    pub fn get_device_role(&self) -> Option<DeviceRole> {
+        self.app_config.device_role
+    }
+
+    pub fn get_channel_number(&self) -> ChannelNumber {
+        self.app_config.channel_number
+    }
+
+    pub fn get_mac_address_mode(&self) -> MacAddressMode {
+        self.app_config.mac_address_mode
+    }
+
+    /// Static STS key configured by the host, if the session uses static STS.
+    pub fn get_static_sts_key(&self) -> Option<StsKey> {
+        match (
+            self.app_config.sts_config,
+            self.app_config.vendor_id,
+            self.app_config.static_sts_iv,
+        ) {
+            (StsConfig::Static, Some(vendor_id), Some(static_sts_iv)) => {
+                let mut key = [0; 8];
+                key[..2].copy_from_slice(&vendor_id);
+                key[2..].copy_from_slice(&static_sts_iv);
+                Some(StsKey(key))
+            }
+            _ => None,
+        }
+    }
+
     pub fn get_dst_mac_addresses(&self) -> &Vec<MacAddress> {
         &self.app_config.dst_mac_addresses
     }
 
+    /// Ranging interval of the session, if ranging is started.
+    /// Controlee sessions have no ranging task, they range at the
+    /// interval of their controller while active.
+    pub fn get_active_ranging_interval(&self) -> Option<Duration> {
+        let controlee = self.app_config.device_type == Some(DeviceType::Controlee);
+        (self.state == SessionState::SessionStateActive
+            && (self.ranging_task.is_some() || controlee))
+            .then_some(self.app_config.ranging_interval)
+    }
+
+    pub fn get_link_budget(&self) -> LinkBudget {
+        LinkBudget {
+            channel_number: self.app_config.channel_number,
+            tx_adaptive_payload_power: self.app_config.tx_adaptive_payload_power,
+        }
+    }
+
     pub fn init(&mut self) {
         self.set_state(SessionState::SessionStateInit);
     }
@@ -325,6 +476,7 @@ impl Session {
     fn command_set_app_config(
         &mut self,
         cmd: SessionSetAppConfigCmdPacket,
+        profile: &DeviceProfile,
     ) -> SessionSetAppConfigRspPacket {
         // TODO properly handle these asserts
         println!(
@@ -338,7 +490,7 @@ impl Session {
             (StatusCode::UciStatusRejected, Vec::new())
         } else {
             let mut app_config = self.app_config.clone();
-            let invalid_parameters = app_config.extend(cmd.get_parameters());
+            let invalid_parameters = app_config.extend(cmd.get_parameters(), profile);
             if invalid_parameters.is_empty() {
                 self.app_config = app_config;
                 self.set_state(SessionState::SessionStateIdle);
@@ -419,7 +571,7 @@ impl Session {
         let status = {
             if (self.state != SessionState::SessionStateActive
                 && self.state != SessionState::SessionStateIdle)
-                || self.app_config.device_type != DeviceType::Controller
+                || self.app_config.device_type != Some(DeviceType::Controller)
                 || (self.app_config.multi_node_mode != MultiNodeMode::OneToMany
                     && self.app_config.multi_node_mode != MultiNodeMode::ManyToMany)
             {
@@ -452,24 +604,37 @@ impl Session {
             assert!(self.ranging_task.is_none());
             assert_eq!(self.state, SessionState::SessionStateIdle);
 
-            let session_id = self.id;
-            let ranging_interval = self.app_config.ranging_interval;
-            let device_handle = self.device_handle;
-            let tx = self.pica_tx.clone();
-            self.ranging_task = Some(tokio::spawn(async move {
-                loop {
-                    time::sleep(ranging_interval).await;
-                    tx.send(PicaCommand::Ranging(device_handle, session_id))
-                        .await
-                        .unwrap();
-                }
-            }));
+            self.start_ranging_task();
             self.set_state(SessionState::SessionStateActive);
             StatusCode::UciStatusOk
         };
         RangeStartRspBuilder { status }.build()
     }
 
+    fn start_ranging_task(&mut self) {
+        // Controlee sessions are driven by their controller.
+        if self.app_config.device_type == Some(DeviceType::Controlee) {
+            return;
+        }
+        let session_id = self.id;
+        let ranging_interval = self.app_config.ranging_interval;
+        let device_handle = self.device_handle;
+        let tx = self.pica_tx.clone();
+        let clock = self.clock.clone();
+        // Deadlines are absolute so that stepping the clock
+        // by N intervals triggers exactly N rangings.
+        let mut deadline = self.clock.now();
+        self.ranging_task = Some(tokio::spawn(async move {
+            loop {
+                deadline += ranging_interval;
+                clock.sleep_until(deadline).await;
+                tx.send(PicaCommand::Ranging(device_handle, session_id))
+                    .await
+                    .unwrap();
+            }
+        }));
+    }
+
     fn stop_ranging_task(&mut self) {
         if let Some(handle) = &self.ranging_task {
             handle.abort();
@@ -507,10 +672,14 @@ impl Session {
         .build()
     }
 
-    pub fn session_command(&mut self, cmd: SessionCommandPacket) -> SessionResponsePacket {
+    pub fn session_command(
+        &mut self,
+        cmd: SessionCommandPacket,
+        profile: &DeviceProfile,
+    ) -> SessionResponsePacket {
         match cmd.specialize() {
             SessionCommandChild::SessionSetAppConfigCmd(cmd) => {
-                self.command_set_app_config(cmd).into()
+                self.command_set_app_config(cmd, profile).into()
             }
             SessionCommandChild::SessionGetAppConfigCmd(cmd) => {
                 self.command_get_app_config(cmd).into()
@@ -535,6 +704,147 @@ impl Session {
     }
 }
 
+impl Session {
+    pub fn info(&self) -> SessionInfo {
+        let app_config = &self.app_config;
+        SessionInfo {
+            id: self.id,
+            session_type: self.session_type.to_u8().unwrap(),
+            state: self.state.to_u8().unwrap(),
+            sequence_number: self.sequence_number,
+            ranging_count: self.ranging_count,
+            app_config: AppConfigInfo {
+                device_type: app_config.device_type,
+                device_role: app_config.device_role,
+                mac_address_mode: app_config.mac_address_mode.to_u8().unwrap(),
+                device_mac_address: app_config.device_mac_address,
+                number_of_controlees: app_config.number_of_controlees,
+                dst_mac_addresses: app_config.dst_mac_addresses.clone(),
+                ranging_interval: app_config.ranging_interval.as_millis() as u64,
+                slot_duration: app_config.slot_duration,
+                channel_number: app_config.channel_number.to_u8().unwrap(),
+                multi_node_mode: app_config.multi_node_mode.to_u8().unwrap(),
+                tx_adaptive_payload_power: app_config.tx_adaptive_payload_power,
+                sts_config: app_config.sts_config.to_u8().unwrap(),
+                vendor_id: app_config.vendor_id,
+                static_sts_iv: app_config.static_sts_iv,
+            },
+            raw_app_config: app_config
+                .raw
+                .iter()
+                .map(|(id, value)| (id.to_u8().unwrap(), value.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn snapshot(&self) -> SessionSnapshot {
+        SessionSnapshot {
+            id: self.id,
+            session_type: self.session_type.to_u8().unwrap(),
+            state: self.state.to_u8().unwrap(),
+            sequence_number: self.sequence_number,
+            app_config: self
+                .app_config
+                .raw
+                .iter()
+                .map(|(id, value)| (id.to_u8().unwrap(), value.clone()))
+                .collect(),
+        }
+    }
+
+    /// Create a session from a snapshot. The session is created in the
+    /// Deinit state, the state of the snapshot is returned to be applied
+    /// with `restore_state`.
+    pub fn from_snapshot(
+        snapshot: &SessionSnapshot,
+        device_handle: usize,
+        tx: mpsc::Sender<UciPacketPacket>,
+        pica_tx: mpsc::Sender<PicaCommand>,
+        clock: Clock,
+    ) -> Result<(Self, SessionState), String> {
+        let session_type = SessionType::from_u8(snapshot.session_type)
+            .ok_or(format!("invalid session type {}", snapshot.session_type))?;
+        let state = SessionState::from_u8(snapshot.state)
+            .ok_or(format!("invalid session state {}", snapshot.state))?;
+
+        // The MAC address mode determines the format of the MAC addresses,
+        // and is applied first.
+        let mut parameters = snapshot
+            .app_config
+            .iter()
+            .map(|(id, value)| {
+                AppConfigTlvType::from_u8(*id)
+                    .filter(|_| !value.is_empty())
+                    .map(|id| (id, value))
+                    .ok_or(format!("invalid app config parameter {}", id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        parameters.sort_by_key(|(id, _)| (*id != AppConfigTlvType::MacAddressMode, *id as u8));
+
+        let mut app_config = AppConfig::default();
+        for (id, value) in parameters {
+            app_config
+                .set_config(id, value)
+                .map_err(|_| format!("invalid app config parameter {}", id))?;
+        }
+
+        let mut session =
+            Session::new(snapshot.id, session_type, device_handle, tx, pica_tx, clock);
+        session.sequence_number = snapshot.sequence_number;
+        session.app_config = app_config;
+        Ok((session, state))
+    }
+
+    /// Stop ranging when the device fails, the active session
+    /// returns to the idle state.
+    pub fn stop(&mut self) {
+        self.stop_ranging_task();
+        if self.state == SessionState::SessionStateActive {
+            self.set_state(SessionState::SessionStateIdle);
+        }
+    }
+
+    /// Stop ranging while the host is disconnected, the session
+    /// stays in its current state.
+    pub fn suspend(&mut self) {
+        self.stop_ranging_task();
+    }
+
+    /// Attach the session to a reconnected host, report the current
+    /// state and restart ranging if the session is active.
+    pub fn resume(&mut self, device_handle: usize, tx: mpsc::Sender<UciPacketPacket>) {
+        self.device_handle = device_handle;
+        self.tx = tx.clone();
+        if self.state == SessionState::SessionStateActive && self.ranging_task.is_none() {
+            self.start_ranging_task();
+        }
+        let session_id = self.id;
+        let session_state = self.state;
+        tokio::spawn(async move {
+            let _ = tx
+                .send(
+                    SessionStatusNtfBuilder {
+                        session_id,
+                        session_state,
+                        reason_code: ReasonCode::StateChangeWithSessionManagementCommands,
+                    }
+                    .build()
+                    .into(),
+                )
+                .await;
+        });
+    }
+
+    /// Apply the state of a session created from a snapshot,
+    /// and restart ranging if the session is active.
+    pub fn restore_state(&mut self, state: SessionState) {
+        if state == SessionState::SessionStateActive {
+            self.start_ranging_task();
+        }
+        self.set_state(state);
+    }
+}
+
 impl Drop for Session {
     fn drop(&mut self) {
         // Make sure to abort the ranging task when dropping the session,
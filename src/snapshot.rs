@@ -0,0 +1,169 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshot of the full state of the simulator.
+
+use crate::antenna::AntennaPattern;
+use crate::mac_address::MacAddress;
+use crate::noise::NoiseModel;
+use crate::scene::Scene;
+use crate::uci_packets::DeviceState;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// State of a UCI session. Enumerations are stored
+/// with their UCI values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub id: u32,
+    pub session_type: u8,
+    pub state: u8,
+    pub sequence_number: u32,
+    /// App configuration parameters set by the host,
+    /// indexed by TLV type.
+    pub app_config: BTreeMap<u8, Vec<u8>>,
+}
+
+/// State of a UCI device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceSnapshot {
+    pub mac_address: MacAddress,
+    pub state: u8,
+    /// Device configuration parameters, indexed by TLV type.
+    pub config: BTreeMap<u8, Vec<u8>>,
+    pub country_code: [u8; 2],
+    pub sessions: Vec<SessionSnapshot>,
+}
+
+impl DeviceSnapshot {
+    /// State of a device after reset.
+    pub fn reset(mac_address: MacAddress) -> Self {
+        DeviceSnapshot {
+            mac_address,
+            state: DeviceState::DeviceStateReady.to_u8().unwrap(),
+            config: BTreeMap::new(),
+            country_code: Default::default(),
+            sessions: Vec::new(),
+        }
+    }
+}
+
+/// Noise model of a device, or of the link between two devices.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoiseSnapshot {
+    pub mac_address: MacAddress,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_mac_address: Option<MacAddress>,
+    pub model: NoiseModel,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AntennaSnapshot {
+    pub mac_address: MacAddress,
+    pub pattern: AntennaPattern,
+}
+
+/// Full state of the simulator. Restoring a snapshot restarts the
+/// trajectories and the noise sequences from their beginning.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Anchors, positions of the UCI devices, obstacles and trajectories.
+    pub scene: Scene,
+    /// State of the connected UCI devices.
+    #[serde(default)]
+    pub uci_devices: Vec<DeviceSnapshot>,
+    #[serde(default)]
+    pub noise: Vec<NoiseSnapshot>,
+    #[serde(default)]
+    pub antennas: Vec<AntennaSnapshot>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::Session;
+    use crate::Clock;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn session_round_trip() {
+        let snapshot = SessionSnapshot {
+            id: 42,
+            session_type: 0,
+            state: 0,
+            sequence_number: 7,
+            app_config: BTreeMap::from([
+                (0x5, vec![1]),
+                (0x6, vec![0, 1, 2, 3, 4, 5, 6, 7]),
+                (0x7, vec![0, 2, 3, 4, 5, 6, 7, 8]),
+                (0x9, vec![100, 0, 0, 0]),
+                (0x26, vec![2]),
+            ]),
+        };
+        let (tx, _) = mpsc::channel(1);
+        let (pica_tx, _) = mpsc::channel(1);
+        let (session, state) =
+            Session::from_snapshot(&snapshot, 0, tx.clone(), pica_tx.clone(), Clock::new())
+                .unwrap();
+        assert_eq!(state.to_u8(), Some(0));
+        assert_eq!(
+            session.get_dst_mac_addresses(),
+            &vec![MacAddress::Extend([0, 2, 3, 4, 5, 6, 7, 8])]
+        );
+        assert_eq!(
+            session.snapshot(),
+            SessionSnapshot {
+                state: 1,
+                ..snapshot.clone()
+            }
+        );
+
+        let mut invalid = snapshot;
+        invalid.app_config.insert(0x9, vec![]);
+        assert!(Session::from_snapshot(&invalid, 0, tx, pica_tx, Clock::new()).is_err());
+    }
+
+    #[test]
+    fn malformed_app_config() {
+        let (tx, _) = mpsc::channel(1);
+        let (pica_tx, _) = mpsc::channel(1);
+        for (id, value) in [
+            (0x9, vec![1]),       // Ranging interval length
+            (0x4, vec![7]),       // Unsupported channel
+            (0x5, vec![9]),       // Too many controlees
+            (0x26, vec![1]),      // MAC address mode 1
+            (0x6, vec![1, 2, 3]), // Device MAC address length
+            (0x3, vec![3]),       // Unknown multi node mode
+            (0x0, vec![0, 1]),    // Device type length
+            (0x1c, vec![2]),      // Adaptive payload power
+        ] {
+            let snapshot = SessionSnapshot {
+                id: 42,
+                session_type: 0,
+                state: 0,
+                sequence_number: 0,
+                app_config: BTreeMap::from([(id, value)]),
+            };
+            assert!(Session::from_snapshot(
+                &snapshot,
+                0,
+                tx.clone(),
+                pica_tx.clone(),
+                Clock::new()
+            )
+            .is_err());
+        }
+    }
+}
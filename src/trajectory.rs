@@ -0,0 +1,271 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scripted motion of devices along a list of waypoints.
+
+use crate::position::{LengthUnit, Position};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Interval between two updates of the positions of moving devices.
+pub const MOTION_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Interpolation of the position between two waypoints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Straight segments between the waypoints.
+    #[default]
+    Linear,
+    /// Catmull-Rom spline going through all the waypoints.
+    Spline,
+}
+
+/// Position reached by a device along its trajectory.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub position: Position,
+    /// Time at which the waypoint is reached, in seconds
+    /// from the start of the trajectory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<f32>,
+    /// Speed of the device from the previous waypoint, in cm/s.
+    /// Only used when the time is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+}
+
+/// Trajectory followed by a device or an anchor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trajectory {
+    pub waypoints: Vec<Waypoint>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// When set, the trajectory restarts from the first waypoint after
+    /// reaching the last one. Otherwise the device stops at the last waypoint.
+    #[serde(default, rename = "loop")]
+    pub looping: bool,
+}
+
+/// Trajectory being followed by a device.
+#[derive(Debug, Clone)]
+pub struct Motion {
+    trajectory: Trajectory,
+    /// Time at which each waypoint is reached, in seconds.
+    times: Vec<f32>,
+    /// Simulation time at which the trajectory is started.
+    start: Duration,
+}
+
+/// Uniform Catmull-Rom spline between p1 and p2.
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2. * p1
+        + (p2 - p0) * t
+        + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2
+        + (3. * p1 - p0 - 3. * p2 + p3) * t3)
+}
+
+impl Motion {
+    /// Start following the trajectory at the selected simulation time.
+    /// Fails if the timing of the waypoints is invalid.
+    pub fn new(trajectory: Trajectory, start: Duration) -> Result<Self, String> {
+        let mut times: Vec<f32> = Vec::with_capacity(trajectory.waypoints.len());
+        for (index, waypoint) in trajectory.waypoints.iter().enumerate() {
+            let time = match (times.last(), waypoint.time, waypoint.speed) {
+                (None, time, _) => time.unwrap_or(0.),
+                (Some(_), Some(time), _) => time,
+                (Some(previous), None, Some(speed)) if speed > 0. => {
+                    let distance = waypoint
+                        .position
+                        .point()
+                        .distance(trajectory.waypoints[index - 1].position.point());
+                    previous + distance / LengthUnit::Centimeter.to_meters(speed)
+                }
+                (Some(_), None, _) => {
+                    return Err(format!(
+                        "waypoint {} has neither a time nor a positive speed",
+                        index
+                    ))
+                }
+            };
+            if !time.is_finite() || time < 0. || times.last().map_or(false, |t| time <= *t) {
+                return Err(format!(
+                    "waypoint {} is not reached after the previous one",
+                    index
+                ));
+            }
+            times.push(time);
+        }
+
+        if times.is_empty() {
+            return Err("trajectory has no waypoints".to_string());
+        }
+
+        Ok(Motion {
+            trajectory,
+            times,
+            start,
+        })
+    }
+
+    pub fn trajectory(&self) -> &Trajectory {
+        &self.trajectory
+    }
+
+    fn duration(&self) -> f32 {
+        *self.times.last().unwrap()
+    }
+
+    fn elapsed(&self, now: Duration) -> f32 {
+        now.saturating_sub(self.start).as_secs_f32()
+    }
+
+    /// Return true if the device reached the end of a one-shot trajectory.
+    pub fn is_finished(&self, now: Duration) -> bool {
+        !self.trajectory.looping && self.elapsed(now) >= self.duration()
+    }
+
+    /// Position of the device at the selected simulation time.
+    pub fn position(&self, now: Duration) -> Position {
+        self.position_at(self.elapsed(now))
+    }
+
+    fn position_at(&self, mut time: f32) -> Position {
+        let waypoints = &self.trajectory.waypoints;
+        let last = waypoints.len() - 1;
+
+        if self.trajectory.looping && self.duration() > 0. {
+            time %= self.duration();
+        }
+        if time <= self.times[0] {
+            return waypoints[0].position;
+        }
+        if time >= self.times[last] {
+            return waypoints[last].position;
+        }
+
+        let index = self.times.partition_point(|t| *t <= time) - 1;
+        let (from, to) = (&waypoints[index], &waypoints[index + 1]);
+        let t = (time - self.times[index]) / (self.times[index + 1] - self.times[index]);
+
+        let point = match self.trajectory.interpolation {
+            Interpolation::Linear => from.position.point().lerp(to.position.point(), t),
+            Interpolation::Spline => catmull_rom(
+                waypoints[index.saturating_sub(1)].position.point(),
+                from.position.point(),
+                to.position.point(),
+                waypoints[(index + 2).min(last)].position.point(),
+                t,
+            ),
+        };
+        let rotation = from.position.rotation().slerp(to.position.rotation(), t);
+        Position::from_point_rotation(point, rotation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waypoint(x: i16, time: Option<f32>, speed: Option<f32>) -> Waypoint {
+        Waypoint {
+            position: Position::new(x, 0, 0, 0, 0, 0),
+            time,
+            speed,
+        }
+    }
+
+    fn trajectory(waypoints: Vec<Waypoint>, looping: bool) -> Trajectory {
+        Trajectory {
+            waypoints,
+            interpolation: Interpolation::Linear,
+            looping,
+        }
+    }
+
+    #[test]
+    fn timing() {
+        let motion = Motion::new(
+            trajectory(
+                vec![
+                    waypoint(0, None, None),
+                    waypoint(100, None, Some(50.)),
+                    waypoint(100, Some(5.), None),
+                ],
+                false,
+            ),
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(motion.times, vec![0., 2., 5.]);
+        assert_eq!(motion.position_at(1.).point().x, 0.5);
+        assert_eq!(motion.position_at(3.).point().x, 1.);
+        assert_eq!(motion.position_at(10.).point().x, 1.);
+    }
+
+    #[test]
+    fn looping() {
+        let motion = Motion::new(
+            trajectory(
+                vec![waypoint(0, None, None), waypoint(100, Some(4.), None)],
+                true,
+            ),
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(motion.position_at(5.).point().x, 0.25);
+        assert!(!motion.is_finished(motion.start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn spline() {
+        let mut trajectory = trajectory(
+            vec![
+                waypoint(0, None, None),
+                waypoint(100, Some(1.), None),
+                waypoint(200, Some(2.), None),
+                waypoint(300, Some(3.), None),
+            ],
+            false,
+        );
+        trajectory.interpolation = Interpolation::Spline;
+        let motion = Motion::new(trajectory, Duration::ZERO).unwrap();
+        assert_eq!(motion.position_at(1.).point().x, 1.);
+        assert!((motion.position_at(1.5).point().x - 1.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Motion::new(trajectory(vec![], false), Duration::ZERO).is_err());
+        assert!(Motion::new(
+            trajectory(
+                vec![waypoint(0, None, None), waypoint(100, None, None)],
+                false
+            ),
+            Duration::ZERO
+        )
+        .is_err());
+        assert!(Motion::new(
+            trajectory(
+                vec![waypoint(0, Some(2.), None), waypoint(100, Some(1.), None)],
+                false
+            ),
+            Duration::ZERO
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! UCI devices simulated in process. A driver task plays the role of
+//! the UWB host: it starts a ranging session, and reports the received
+//! notifications as events.
+
+use crate::session::{ChannelNumber, DeviceRole, DeviceType, MAX_NUMBER_OF_CONTROLEES};
+use crate::uci_packets::*;
+use crate::{MacAddress, PicaCommand, PicaEvent, Position};
+use num_traits::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+
+/// Ranging session started by a virtual device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VirtualSession {
+    pub session_id: u32,
+    pub device_type: DeviceType,
+    pub device_role: DeviceRole,
+    /// Short addresses of the peers.
+    pub dst_mac_addresses: Vec<MacAddress>,
+    /// Ranging interval in milliseconds.
+    pub ranging_interval: u32,
+    pub channel_number: u8,
+}
+
+impl Default for VirtualSession {
+    fn default() -> Self {
+        VirtualSession {
+            session_id: 1,
+            device_type: DeviceType::Controller,
+            device_role: DeviceRole::Initiator,
+            dst_mac_addresses: Vec::new(),
+            ranging_interval: 200,
+            channel_number: 9,
+        }
+    }
+}
+
+/// Configuration of a virtual device.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VirtualDevice {
+    pub position: Position,
+    /// Session started when the device is created. The device
+    /// stays idle when unset.
+    pub session: Option<VirtualSession>,
+}
+
+impl VirtualSession {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.dst_mac_addresses.len() > MAX_NUMBER_OF_CONTROLEES {
+            return Err(format!(
+                "too many destination addresses, the maximum is {}",
+                MAX_NUMBER_OF_CONTROLEES
+            ));
+        }
+        if let Some(mac_address) = self
+            .dst_mac_addresses
+            .iter()
+            .find(|mac_address| matches!(mac_address, MacAddress::Extend(_)))
+        {
+            return Err(format!("unsupported extended address {}", mac_address));
+        }
+        if ChannelNumber::from_u8(self.channel_number).is_none() {
+            return Err(format!("unsupported channel {}", self.channel_number));
+        }
+        if self.ranging_interval == 0 {
+            return Err("null ranging interval".to_string());
+        }
+        Ok(())
+    }
+
+    /// Commands sent by the driver to start the session.
+    pub(crate) fn commands(&self, mac_address: MacAddress) -> Vec<UciCommandPacket> {
+        let short_address = |mac_address: &MacAddress| match mac_address {
+            MacAddress::Short(address) => address.to_vec(),
+            MacAddress::Extend(_) => unreachable!(),
+        };
+        let parameter = |id: AppConfigTlvType, value: Vec<u8>| AppConfigParameter {
+            id: id.to_u8().unwrap(),
+            value,
+        };
+        vec![
+            SessionInitCmdBuilder {
+                session_id: self.session_id,
+                session_type: SessionType::FiraRangingSession,
+            }
+            .build()
+            .into(),
+            SessionSetAppConfigCmdBuilder {
+                session_id: self.session_id,
+                parameters: vec![
+                    parameter(AppConfigTlvType::DeviceType, vec![self.device_type as u8]),
+                    parameter(AppConfigTlvType::DeviceRole, vec![self.device_role as u8]),
+                    parameter(
+                        AppConfigTlvType::MultiNodeMode,
+                        vec![(self.dst_mac_addresses.len() > 1) as u8],
+                    ),
+                    parameter(AppConfigTlvType::MacAddressMode, vec![0x00]),
+                    parameter(AppConfigTlvType::ChannelNumber, vec![self.channel_number]),
+                    parameter(
+                        AppConfigTlvType::RangingInterval,
+                        self.ranging_interval.to_le_bytes().to_vec(),
+                    ),
+                    parameter(
+                        AppConfigTlvType::DeviceMacAddress,
+                        short_address(&mac_address),
+                    ),
+                    parameter(
+                        AppConfigTlvType::NoOfControlee,
+                        vec![self.dst_mac_addresses.len() as u8],
+                    ),
+                    parameter(
+                        AppConfigTlvType::DstMacAddress,
+                        self.dst_mac_addresses
+                            .iter()
+                            .flat_map(short_address)
+                            .collect(),
+                    ),
+                ],
+            }
+            .build()
+            .into(),
+            RangeStartCmdBuilder {
+                session_id: self.session_id,
+            }
+            .build()
+            .into(),
+        ]
+    }
+}
+
+/// Measurement reported in a range data notification.
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeMeasurement {
+    pub mac_address: MacAddress,
+    pub status: u8,
+    pub nlos: bool,
+    pub distance: u16,
+    pub azimuth: i16,
+    pub elevation: i16,
+}
+
+/// Notification received by a virtual device.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "notification", rename_all = "snake_case")]
+pub enum DeviceNotification {
+    DeviceStatus {
+        state: u8,
+    },
+    SessionStatus {
+        session_id: u32,
+        state: u8,
+        reason_code: u8,
+    },
+    RangeData {
+        session_id: u32,
+        sequence_number: u32,
+        measurements: Vec<RangeMeasurement>,
+    },
+}
+
+impl DeviceNotification {
+    pub(crate) fn from_packet(packet: &UciPacketPacket) -> Option<Self> {
+        // Notifications built from a raw payload, like the UCI 2.0 range
+        // data notifications, are only specialized once parsed again.
+        let packet = UciPacketPacket::parse(&packet.clone().to_bytes()).ok()?;
+        let notification = match packet.specialize() {
+            UciPacketChild::UciNotification(notification) => notification,
+            _ => return None,
+        };
+        match notification.specialize() {
+            UciNotificationChild::CoreNotification(notification) => {
+                match notification.specialize() {
+                    CoreNotificationChild::DeviceStatusNtf(ntf) => {
+                        Some(DeviceNotification::DeviceStatus {
+                            state: ntf.get_device_state().to_u8().unwrap(),
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            UciNotificationChild::SessionNotification(notification) => {
+                match notification.specialize() {
+                    SessionNotificationChild::SessionStatusNtf(ntf) => {
+                        Some(DeviceNotification::SessionStatus {
+                            session_id: ntf.get_session_id(),
+                            state: ntf.get_session_state().to_u8().unwrap(),
+                            reason_code: ntf.get_reason_code().to_u8().unwrap(),
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            UciNotificationChild::RangingNotification(notification) => {
+                match notification.specialize() {
+                    RangingNotificationChild::RangeDataNtf(ntf) => match ntf.specialize() {
+                        RangeDataNtfChild::ShortMacTwoWayRangeDataNtf(ntf) => {
+                            Some(DeviceNotification::RangeData {
+                                session_id: ntf.get_session_id(),
+                                sequence_number: ntf.get_sequence_number(),
+                                measurements: ntf
+                                    .get_two_way_ranging_measurements()
+                                    .iter()
+                                    .map(|measurement| RangeMeasurement {
+                                        mac_address: MacAddress::Short(
+                                            measurement.mac_address.to_be_bytes(),
+                                        ),
+                                        status: measurement.status.to_u8().unwrap(),
+                                        nlos: measurement.nlos != 0,
+                                        distance: measurement.distance,
+                                        azimuth: measurement.aoa_azimuth as i16,
+                                        elevation: measurement.aoa_elevation as i16,
+                                    })
+                                    .collect(),
+                            })
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Status of the response to a command sent by the driver.
+fn response_status(packet: &UciPacketPacket) -> Option<StatusCode> {
+    let response = match packet.specialize() {
+        UciPacketChild::UciResponse(response) => response,
+        _ => return None,
+    };
+    match response.specialize() {
+        UciResponseChild::SessionResponse(response) => match response.specialize() {
+            SessionResponseChild::SessionInitRsp(rsp) => Some(rsp.get_status()),
+            SessionResponseChild::SessionSetAppConfigRsp(rsp) => Some(rsp.get_status()),
+            _ => None,
+        },
+        UciResponseChild::RangingResponse(response) => match response.specialize() {
+            RangingResponseChild::RangeStartRsp(rsp) => Some(rsp.get_status()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Drive a virtual device: start the configured session, then report
+/// the notifications as events until the device is removed.
+pub async fn drive(
+    device_handle: usize,
+    mac_address: MacAddress,
+    session: Option<VirtualSession>,
+    pica_tx: mpsc::Sender<PicaCommand>,
+    mut packet_rx: mpsc::Receiver<UciPacketPacket>,
+    event_tx: broadcast::Sender<PicaEvent>,
+) {
+    let report = |packet: &UciPacketPacket| {
+        if let Some(notification) = DeviceNotification::from_packet(packet) {
+            let _ = event_tx.send(PicaEvent::VirtualDeviceNotification {
+                mac_address,
+                notification,
+            });
+        }
+    };
+
+    let commands = session
+        .map(|session| session.commands(mac_address))
+        .unwrap_or_default();
+    for cmd in commands {
+        if pica_tx
+            .send(PicaCommand::Command(device_handle, cmd))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        let status = loop {
+            match packet_rx.recv().await {
+                Some(packet) => match response_status(&packet) {
+                    Some(status) => break status,
+                    None => report(&packet),
+                },
+                None => return,
+            }
+        };
+        if status != StatusCode::UciStatusOk {
+            println!(
+                "[{}] Virtual device command failed: {}",
+                device_handle, status
+            );
+            break;
+        }
+    }
+
+    while let Some(packet) = packet_rx.recv().await {
+        report(&packet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        assert!(VirtualSession::default().validate().is_ok());
+        let session = VirtualSession {
+            dst_mac_addresses: vec![MacAddress::Extend([0; 8])],
+            ..Default::default()
+        };
+        assert!(session.validate().is_err());
+        let session = VirtualSession {
+            channel_number: 7,
+            ..Default::default()
+        };
+        assert!(session.validate().is_err());
+        let session = VirtualSession {
+            dst_mac_addresses: vec![MacAddress::Short([0, 1]); MAX_NUMBER_OF_CONTROLEES + 1],
+            ..Default::default()
+        };
+        assert!(session.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn drive_stops_on_failure() {
+        let (pica_tx, mut pica_rx) = mpsc::channel(1);
+        let (packet_tx, packet_rx) = mpsc::channel(1);
+        let (event_tx, mut event_rx) = broadcast::channel(4);
+        let mac_address = MacAddress::Short([0, 1]);
+        let driver = tokio::spawn(drive(
+            1,
+            mac_address,
+            Some(VirtualSession::default()),
+            pica_tx,
+            packet_rx,
+            event_tx,
+        ));
+
+        assert!(matches!(
+            pica_rx.recv().await,
+            Some(PicaCommand::Command(1, cmd)) if cmd.get_opcode() == 0x0
+        ));
+        packet_tx
+            .send(
+                DeviceStatusNtfBuilder {
+                    device_state: DeviceState::DeviceStateReady,
+                }
+                .build()
+                .into(),
+            )
+            .await
+            .unwrap();
+        packet_tx
+            .send(
+                SessionInitRspBuilder {
+                    status: StatusCode::UciStatusRejected,
+                }
+                .build()
+                .into(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            event_rx.recv().await,
+            Ok(PicaEvent::VirtualDeviceNotification {
+                notification: DeviceNotification::DeviceStatus { state: 1 },
+                ..
+            })
+        ));
+
+        drop(packet_tx);
+        driver.await.unwrap();
+        assert!(pica_rx.recv().await.is_none());
+    }
+}
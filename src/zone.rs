@@ -0,0 +1,112 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::position::{LengthUnit, Point, Position};
+use serde::{Deserialize, Serialize};
+
+/// Geofence placed in the scene. Events are emitted when devices
+/// enter or exit the zone. Zones do not affect the measurements.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Zone {
+    Sphere {
+        center: Point,
+        /// Radius of the sphere, in cm.
+        radius: f32,
+    },
+    /// Axis aligned box.
+    Box {
+        /// Corner of the box with the lowest coordinates.
+        min: Point,
+        /// Corner of the box with the highest coordinates.
+        max: Point,
+    },
+}
+
+impl Zone {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Zone::Sphere { radius, .. } if radius.is_nan() || *radius < 0. => {
+                Err(format!("invalid radius {}", radius))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Return true if the position is inside the zone,
+    /// boundary included.
+    pub fn contains(&self, position: &Position) -> bool {
+        let point = position.point();
+        match self {
+            Zone::Sphere { center, radius } => {
+                point.distance(center.to_meters()) <= LengthUnit::Centimeter.to_meters(*radius)
+            }
+            Zone::Box { min, max } => {
+                let (a, b) = (min.to_meters(), max.to_meters());
+                point.cmpge(a.min(b)).all() && point.cmple(a.max(b)).all()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere() {
+        let zone = Zone::Sphere {
+            center: Point {
+                x: 100.,
+                y: 0.,
+                z: 0.,
+            },
+            radius: 50.,
+        };
+        assert!(zone.contains(&Position::new(100, 0, 0, 0, 0, 0)));
+        assert!(zone.contains(&Position::new(150, 0, 0, 0, 0, 0)));
+        assert!(!zone.contains(&Position::new(0, 0, 0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn box_corners() {
+        // Corners are accepted in any order.
+        let zone = Zone::Box {
+            min: Point {
+                x: 100.,
+                y: 100.,
+                z: 100.,
+            },
+            max: Point {
+                x: -100.,
+                y: -100.,
+                z: 0.,
+            },
+        };
+        assert!(zone.contains(&Position::new(0, 0, 50, 0, 0, 0)));
+        assert!(zone.contains(&Position::new(100, -100, 0, 0, 0, 0)));
+        assert!(!zone.contains(&Position::new(0, 0, 150, 0, 0, 0)));
+    }
+
+    #[test]
+    fn validate() {
+        let zone = |radius| Zone::Sphere {
+            center: Point::default(),
+            radius,
+        };
+        assert!(zone(0.).validate().is_ok());
+        assert!(zone(-1.).validate().is_err());
+        assert!(zone(f32::NAN).validate().is_err());
+    }
+}